@@ -0,0 +1,128 @@
+use std::fmt::Display;
+use std::io::Read;
+use std::path::PathBuf;
+
+pub mod repl;
+mod tests_cli;
+
+pub const USAGE: &str = "\
+Usage: nix_interpreter_cli <COMMAND> [--expr <EXPR> | <FILE>]
+
+Commands:
+  eval       Evaluate the input and print the resulting value
+  parse      Print the syntax tree of the input
+  tokenize   Print the token stream of the input
+  repl       Start an interactive session
+
+Pass `-` as the file to read from stdin.";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Eval,
+    Parse,
+    Tokenize,
+    Repl,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Expr(String),
+    File(PathBuf),
+    Stdin,
+}
+impl Input {
+    pub fn read(&self) -> std::io::Result<String> {
+        match self {
+            Input::Expr(expr) => Ok(expr.clone()),
+            Input::File(path) => std::fs::read_to_string(path),
+            Input::Stdin => {
+                let mut buf = String::new();
+                std::io::stdin().read_to_string(&mut buf)?;
+                Ok(buf)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Cli {
+    pub command: Command,
+    pub input: Option<Input>,
+}
+impl Cli {
+    /// Parses the arguments following the program name.
+    pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, UsageError> {
+        let mut args = args.into_iter();
+        let command = match args.next().as_deref() {
+            Some("eval") => Command::Eval,
+            Some("parse") => Command::Parse,
+            Some("tokenize") => Command::Tokenize,
+            Some("repl") => {
+                return match args.next() {
+                    Some(arg) => Err(UsageError::UnexpectedArgument(arg)),
+                    None => Ok(Self {
+                        command: Command::Repl,
+                        input: None,
+                    }),
+                }
+            }
+            Some("-h" | "--help" | "help") => {
+                return Ok(Self {
+                    command: Command::Help,
+                    input: None,
+                })
+            }
+            Some(other) => return Err(UsageError::UnknownCommand(other.to_owned())),
+            None => return Err(UsageError::MissingCommand),
+        };
+
+        let mut input = None;
+        while let Some(arg) = args.next() {
+            let next = match arg.as_str() {
+                "--expr" | "-E" => match args.next() {
+                    Some(expr) => Input::Expr(expr),
+                    None => return Err(UsageError::MissingValue(arg)),
+                },
+                "-" => Input::Stdin,
+                flag if flag.starts_with('-') => return Err(UsageError::UnknownFlag(arg)),
+                path => Input::File(PathBuf::from(path)),
+            };
+            if input.replace(next).is_some() {
+                return Err(UsageError::MultipleInputs);
+            }
+        }
+
+        match input {
+            Some(input) => Ok(Self {
+                command,
+                input: Some(input),
+            }),
+            None => Err(UsageError::MissingInput),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UsageError {
+    MissingCommand,
+    UnknownCommand(String),
+    UnknownFlag(String),
+    UnexpectedArgument(String),
+    MissingValue(String),
+    MissingInput,
+    MultipleInputs,
+}
+impl Display for UsageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsageError::MissingCommand => write!(f, "no command given"),
+            UsageError::UnknownCommand(cmd) => write!(f, "unknown command '{}'", cmd),
+            UsageError::UnknownFlag(flag) => write!(f, "unknown flag '{}'", flag),
+            UsageError::UnexpectedArgument(arg) => write!(f, "unexpected argument '{}'", arg),
+            UsageError::MissingValue(flag) => write!(f, "flag '{}' expects a value", flag),
+            UsageError::MissingInput => write!(f, "expected --expr <EXPR>, a file or '-'"),
+            UsageError::MultipleInputs => write!(f, "only one input may be given"),
+        }
+    }
+}
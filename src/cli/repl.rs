@@ -0,0 +1,206 @@
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use anyhow::{anyhow, bail, Result};
+use nix_interpreter_lib::lexer::tokens::{Token, TokenType};
+use nix_interpreter_lib::lexer::{LexErrorKind, Lexer, TokenStream, Tokenizer};
+use nix_interpreter_lib::parser::{Ast, AstParser, Parser};
+use nix_interpreter_lib::runtime::{global_env, Env, Interpreter, Thunk, Value};
+
+const PROMPT: &str = "nix-repl> ";
+const CONTINUATION_PROMPT: &str = "        > ";
+
+const HELP: &str = "\
+The following commands are available:
+
+  <expr>        Evaluate and print expression
+  <x> = <expr>  Bind expression to variable
+  :p <expr>     Evaluate and print expression recursively
+  :t <expr>     Describe result of evaluation
+  :q            Exit nix-repl";
+
+#[derive(Debug, PartialEq)]
+pub enum Reply {
+    Print(String),
+    Silent,
+    Quit,
+}
+
+pub struct Repl {
+    env: Rc<Env>,
+}
+impl Repl {
+    pub fn new() -> Self {
+        Self {
+            env: Rc::new(Env::new(Some(global_env()), false)),
+        }
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut out: W) -> std::io::Result<()> {
+        let mut lines = input.lines();
+        let mut buf = String::new();
+
+        loop {
+            let prompt = if buf.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION_PROMPT
+            };
+            write!(out, "{}", prompt)?;
+            out.flush()?;
+
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => {
+                    writeln!(out)?;
+                    return Ok(());
+                }
+            };
+            buf.push_str(&line);
+            buf.push('\n');
+            if is_incomplete(&buf) {
+                continue;
+            }
+
+            match self.handle(&buf) {
+                Ok(Reply::Print(s)) => writeln!(out, "{}", s)?,
+                Ok(Reply::Silent) => (),
+                Ok(Reply::Quit) => return Ok(()),
+                Err(err) => eprintln!("error: {}", err),
+            }
+            buf.clear();
+        }
+    }
+
+    /// Runs a single complete input: a `:` command, a `name = expr` binding
+    /// or a bare expression.
+    pub fn handle(&mut self, input: &str) -> Result<Reply> {
+        match split_command(input.trim()) {
+            (Some("q" | "quit"), _) => Ok(Reply::Quit),
+            (Some("?" | "help"), _) => Ok(Reply::Print(HELP.to_owned())),
+            (Some("t"), src) => Ok(Reply::Print(
+                self.eval(src, Interpreter::force)?.type_name().to_owned(),
+            )),
+            (Some("p"), src) => Ok(Reply::Print(
+                self.eval(src, Interpreter::force_deep)?.to_string(),
+            )),
+            (Some(cmd), _) => bail!("unknown command ':{}'", cmd),
+            (None, "") => Ok(Reply::Silent),
+            (None, src) => {
+                let mut lexer = Lexer::new(src);
+                let toks = lex(&mut lexer)?;
+                if let [Token {
+                    typ: TokenType::Ident(name),
+                    ..
+                }, Token {
+                    typ: TokenType::Assign,
+                    ..
+                }, rest @ ..] = toks
+                {
+                    let rest = match rest {
+                        [body @ .., Token {
+                            typ: TokenType::Semicolon,
+                            ..
+                        }] => body,
+                        _ => rest,
+                    };
+                    // Like any other binding, the value is only computed
+                    // once it is used.
+                    let thunk = Thunk::new(parse(rest, src)?, self.env.clone());
+                    self.bind(name, Value::Thunk(Rc::new(thunk)));
+                    return Ok(Reply::Silent);
+                }
+
+                let value = self.evaluate(parse(toks, src)?, Interpreter::force_shallow)?;
+                Ok(Reply::Print(value.display_depth(1)))
+            }
+        }
+    }
+
+    fn eval<F>(&self, src: &str, force: F) -> Result<Value>
+    where
+        F: Fn(&Interpreter, Value) -> Result<Value>,
+    {
+        let mut lexer = Lexer::new(src);
+        self.evaluate(parse(lex(&mut lexer)?, src)?, force)
+    }
+
+    // Evaluates `ast` and forces the result as far as `force` goes.
+    fn evaluate<F>(&self, ast: Ast, force: F) -> Result<Value>
+    where
+        F: Fn(&Interpreter, Value) -> Result<Value>,
+    {
+        let mut interpreter = Interpreter::with_env(&ast, self.env.clone());
+        interpreter
+            .interpret()
+            .and_then(|value| force(&interpreter, value))
+    }
+
+    // Each binding opens a new scope on top of the previous ones, so
+    // rebinding a name shadows the earlier value.
+    fn bind(&mut self, name: &str, value: Value) {
+        self.env = Rc::new(Env::new(Some(self.env.clone()), false));
+        self.env
+            .set(Rc::from(name), value)
+            .expect("a fresh scope has no bindings");
+    }
+}
+
+/// Whether more lines are needed before the input can be parsed, i.e. it
+/// has unclosed brackets or an unterminated string.
+pub fn is_incomplete(input: &str) -> bool {
+    let (_, src) = split_command(input.trim());
+    let mut lexer = Lexer::new(src);
+    match lexer.tokenize() {
+        Ok(toks) => {
+            let depth = toks.iter().fold(0, |depth, tok| match tok.typ {
+                TokenType::OpenBrace
+                | TokenType::Interpolate
+                | TokenType::OpenSquare
+                | TokenType::OpenParen => depth + 1,
+                TokenType::CloseBrace | TokenType::CloseSquare | TokenType::CloseParen => depth - 1,
+                _ => depth,
+            });
+            depth > 0
+        }
+        Err(errors) => errors.iter().any(|err| {
+            matches!(
+                err.kind,
+                LexErrorKind::UnterminatedString | LexErrorKind::UnterminatedIndString
+            )
+        }),
+    }
+}
+
+// Splits `:t expr` into the command name and its argument.
+fn split_command(input: &str) -> (Option<&str>, &str) {
+    match input.strip_prefix(':') {
+        Some(rest) => {
+            let (cmd, arg) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            (Some(cmd), arg.trim())
+        }
+        None => (None, input),
+    }
+}
+
+fn lex<'a>(lexer: &'a mut Lexer<'a>) -> Result<&'a TokenStream<'a>> {
+    lexer.tokenize().map_err(|errors| {
+        anyhow!(errors
+            .iter()
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    })
+}
+
+fn parse<'a>(toks: &'a TokenStream<'a>, src: &'a str) -> Result<Ast> {
+    match AstParser::new(toks).with_source(src).parse() {
+        Ok(ast) => Ok(ast),
+        Err(parse_errors) => bail!(parse_errors
+            .errors
+            .iter()
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>()
+            .join("\n")),
+    }
+}
@@ -0,0 +1,132 @@
+#[cfg(test)]
+mod tests {
+    use crate::cli::repl::*;
+    use crate::cli::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_valid_args() {
+        let test_cases: Vec<(&[&str], Cli)> = vec![
+            (
+                &["eval", "--expr", "1 + 2"],
+                Cli {
+                    command: Command::Eval,
+                    input: Some(Input::Expr("1 + 2".to_owned())),
+                },
+            ),
+            (
+                &["parse", "default.nix"],
+                Cli {
+                    command: Command::Parse,
+                    input: Some(Input::File(PathBuf::from("default.nix"))),
+                },
+            ),
+            (
+                &["tokenize", "-"],
+                Cli {
+                    command: Command::Tokenize,
+                    input: Some(Input::Stdin),
+                },
+            ),
+            (
+                &["--help"],
+                Cli {
+                    command: Command::Help,
+                    input: None,
+                },
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let got = Cli::parse_args(args(input)).unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_invalid_args() {
+        let test_cases: Vec<(&[&str], UsageError)> = vec![
+            (&[], UsageError::MissingCommand),
+            (&["build"], UsageError::UnknownCommand("build".to_owned())),
+            (&["eval"], UsageError::MissingInput),
+            (
+                &["eval", "--expr"],
+                UsageError::MissingValue("--expr".to_owned()),
+            ),
+            (
+                &["eval", "--strict", "a.nix"],
+                UsageError::UnknownFlag("--strict".to_owned()),
+            ),
+            (&["eval", "a.nix", "-"], UsageError::MultipleInputs),
+        ];
+
+        for (input, want) in test_cases {
+            let got = Cli::parse_args(args(input)).unwrap_err();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn repl_keeps_bindings() {
+        let mut repl = Repl::new();
+        let test_cases: Vec<(&str, Reply)> = vec![
+            ("x = true", Reply::Silent),
+            ("y = 2.5;", Reply::Silent),
+            ("x", Reply::Print("true".to_owned())),
+            (":t y", Reply::Print("float".to_owned())),
+            ("x = 1", Reply::Silent),
+            (":p x == 1", Reply::Print("true".to_owned())),
+            ("s = { a = { b = x; }; }", Reply::Silent),
+            ("s", Reply::Print("{ a = { ... }; }".to_owned())),
+            (":p s", Reply::Print("{ a = { b = 1; }; }".to_owned())),
+            (":t s", Reply::Print("set".to_owned())),
+            ("z = throw \"no\"", Reply::Silent),
+            ("x", Reply::Print("1".to_owned())),
+            ("", Reply::Silent),
+            (":q", Reply::Quit),
+        ];
+
+        for (input, want) in test_cases {
+            let got = repl.handle(input).unwrap();
+            assert_eq!(got, want, "{}", input);
+        }
+        // `z` was bound without being evaluated; using it fails.
+        assert_eq!(repl.handle("z").unwrap_err().to_string(), "no, at 1:5");
+    }
+
+    #[test]
+    fn repl_reports_errors() {
+        let mut repl = Repl::new();
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("z", "undefined variable 'z', at 1:1"),
+            (":x", "unknown command ':x'"),
+            ("x = 1 +", "expected expression, found end of input at 1:7"),
+        ];
+
+        for (input, want) in test_cases {
+            let got = repl.handle(input).unwrap_err();
+            assert_eq!(got.to_string(), want);
+        }
+    }
+
+    #[test]
+    fn repl_detects_incomplete_input() {
+        let test_cases: Vec<(&str, bool)> = vec![
+            ("{ a = 1;", true),
+            ("{ a = [ 1\n", true),
+            ("{ a = [ 1 ]; }", false),
+            (":p { a = 1;", true),
+            ("\"abc", true),
+            ("''abc", true),
+            ("1 }", false),
+            ("$", false),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(is_incomplete(input), want, "{}", input);
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub enum CharType {
     Plus,
     Minus,
     Comma,
+    Colon,
     Semicolon,
     OpenParen,
     CloseParen,
@@ -30,6 +31,8 @@ pub enum CharType {
     Question,
     Asterisk,
     Hash,
+    Ampersand,
+    Pipe,
 }
 
 impl TryFrom<char> for CharType {
@@ -37,9 +40,10 @@ impl TryFrom<char> for CharType {
 
     fn try_from(input_char: char) -> Result<Self, Self::Error> {
         match input_char {
-            WHITESPACE => Ok(CharType::Whitespace),
+            WHITESPACE | TAB | CARRIAGE_RETURN => Ok(CharType::Whitespace),
             NEWLINE => Ok(CharType::Newline),
             SEMICOLON => Ok(CharType::Semicolon),
+            COLON => Ok(CharType::Colon),
             EQUALS => Ok(CharType::Equals),
             PLUS => Ok(CharType::Plus),
             MINUS => Ok(CharType::Minus),
@@ -63,6 +67,8 @@ impl TryFrom<char> for CharType {
             QUESTION => Ok(CharType::Question),
             ASTERISK => Ok(CharType::Asterisk),
             HASH => Ok(CharType::Hash),
+            AMPERSAND => Ok(CharType::Ampersand),
+            PIPE => Ok(CharType::Pipe),
             _ => {
                 if let Some(char_token) = get_char(input_char) {
                     return Ok(char_token);
@@ -110,12 +116,15 @@ const OPEN_SQUARE: char = '[';
 const CLOSE_SQUARE: char = ']';
 const COMMA: char = ',';
 const SEMICOLON: char = ';';
+const COLON: char = ':';
 const DOT: char = '.';
 const AT: char = '@';
 const EXCLAMATION: char = '!';
 const DQUOTE: char = '"';
 const SQUOTE: char = '\'';
 const WHITESPACE: char = ' ';
+const TAB: char = '\t';
+const CARRIAGE_RETURN: char = '\r';
 const NEWLINE: char = '\n';
 const FORW_SLASH: char = '/';
 const TILDE: char = '~';
@@ -125,6 +134,8 @@ const DOLLAR: char = '$';
 const QUESTION: char = '?';
 const ASTERISK: char = '*';
 const HASH: char = '#';
+const AMPERSAND: char = '&';
+const PIPE: char = '|';
 
 #[derive(Debug, PartialEq)]
 pub struct InvalidTokenError {
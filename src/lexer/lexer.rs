@@ -1,17 +1,28 @@
 use crate::lexer::chars::*;
+use crate::lexer::span::Span;
 use crate::lexer::tokens::*;
+use std::cell::Cell;
+use std::fmt::Display;
 use std::iter::Iterator;
 
 pub trait Tokenizer<'a> {
-    fn tokenize(&'a mut self) -> &TokenStream;
+    fn tokenize(&'a mut self) -> Result<&'a TokenStream<'a>, Vec<LexError>>;
 }
 
 #[derive(Debug)]
 pub struct Lexer<'a> {
     input_str: &'a str,
     chars: std::iter::Peekable<std::str::CharIndices<'a>>,
-    tokens: Vec<TokenType<'a>>,
+    tokens: Vec<Token<'a>>,
+    errors: Vec<LexError>,
+    line_starts: Vec<usize>,
+    // Byte offset, line and column of the last position `span` located, so
+    // that the next one only counts the characters in between.
+    last_pos: Cell<(usize, usize, usize)>,
+    // Strings whose `${` is still open, innermost last.
+    interpolations: Vec<Interpolation>,
 
+    token_start: usize,
     capture_start: usize,
     capture_end: usize,
 
@@ -23,7 +34,14 @@ impl<'a> Lexer<'a> {
             input_str,
             chars: input_str.char_indices().peekable(),
             tokens: Vec::with_capacity(input_str.len() / 5),
+            errors: vec![],
+            line_starts: std::iter::once(0)
+                .chain(input_str.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            last_pos: Cell::new((0, 1, 1)),
+            interpolations: vec![],
 
+            token_start: 0,
             capture_start: 0,
             capture_end: 0,
 
@@ -31,29 +49,213 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Computes the line and column of a byte range in the input.
+    pub fn span(&self, start: usize, end: usize) -> Span {
+        let line = self
+            .line_starts
+            .partition_point(|&line_start| line_start <= start);
+        let (from, col) = match self.last_pos.get() {
+            (pos, last_line, col) if last_line == line && pos <= start => (pos, col),
+            _ => (self.line_starts[line - 1], 1),
+        };
+        let col = col + self.input_str[from..start].chars().count();
+        self.last_pos.set((start, line, col));
+        Span::new(start, end, line, col)
+    }
+
+    // Pushes a token spanning from the start of the current lexeme up to the
+    // next unconsumed character.
+    fn emit(&mut self, typ: TokenType<'a>) {
+        let end = self.chars.peek().map_or(self.input_str.len(), |&(i, _)| i);
+        let span = self.span(self.token_start, end);
+        self.tokens.push(Token::new(typ, span));
+    }
+
+    // Records an error spanning the current lexeme and carries on lexing.
+    fn error(&mut self, kind: LexErrorKind) {
+        let end = self.chars.peek().map_or(self.input_str.len(), |&(i, _)| i);
+        let span = self.span(self.token_start, end);
+        self.errors.push(LexError::new(kind, span));
+    }
+
+    fn peek_char_type(&mut self) -> Option<CharType> {
+        self.chars
+            .peek()
+            .and_then(|&(_, ch)| CharType::try_from(ch).ok())
+    }
+
+    // Lexes string contents starting at `content_start` up to the closing
+    // delimiter or the next `${`. `str_start` is the opening delimiter of the
+    // string, and `fresh` is set while still in its first segment, so that a
+    // string without interpolations stays a single literal token. Escape
+    // sequences are skipped over but left in the token for the parser to
+    // decode.
+    fn lex_str(&mut self, str_start: usize, content_start: usize, indented: bool, fresh: bool) {
+        while let Some((i, ch)) = self.chars.next() {
+            let closes = match ch {
+                '\\' if !indented => {
+                    self.chars.next();
+                    continue;
+                }
+                '"' => !indented,
+                '\'' if indented && matches!(self.chars.peek(), Some(&(_, '\''))) => {
+                    self.chars.next();
+                    match self.chars.peek() {
+                        // `'''` and `''$`
+                        Some(&(_, '\'' | '$')) => {
+                            self.chars.next();
+                            continue;
+                        }
+                        // `''\n`, `''\t` and the like
+                        Some(&(_, '\\')) => {
+                            self.chars.next();
+                            self.chars.next();
+                            continue;
+                        }
+                        _ => true,
+                    }
+                }
+                // `$${` is a literal `$${` rather than an interpolation.
+                '$' if matches!(self.chars.peek(), Some(&(_, '$'))) => {
+                    self.chars.next();
+                    continue;
+                }
+                _ => false,
+            };
+            if closes {
+                if fresh {
+                    let raw = &self.input_str[content_start..i];
+                    self.emit(if indented {
+                        TokenType::IndStrLiteral(raw)
+                    } else {
+                        TokenType::StrLiteral(raw)
+                    });
+                } else {
+                    self.emit_str_part(content_start, i);
+                    self.token_start = i;
+                    self.emit(TokenType::StrEnd);
+                }
+                return;
+            }
+
+            if ch == '$' && matches!(self.chars.peek(), Some(&(_, '{'))) {
+                if fresh {
+                    let span = self.span(str_start, content_start);
+                    let typ = if indented {
+                        TokenType::IndStrStart
+                    } else {
+                        TokenType::StrStart
+                    };
+                    self.tokens.push(Token::new(typ, span));
+                }
+                self.emit_str_part(content_start, i);
+                self.chars.next();
+                self.token_start = i;
+                self.emit(TokenType::Interpolate);
+                self.interpolations.push(Interpolation {
+                    str_start,
+                    indented,
+                    depth: 0,
+                });
+                return;
+            }
+        }
+
+        self.token_start = str_start;
+        if indented {
+            self.error(LexErrorKind::UnterminatedIndString);
+        } else {
+            self.error(LexErrorKind::UnterminatedString);
+        }
+    }
+
+    fn emit_str_part(&mut self, start: usize, end: usize) {
+        if start < end {
+            let span = self.span(start, end);
+            self.tokens.push(Token::new(
+                TokenType::StrPart(&self.input_str[start..end]),
+                span,
+            ));
+        }
+    }
+
+    // Lexes a number whose first character has already been consumed: an
+    // integer, or a float with a fraction and/or an exponent such as `1.5`,
+    // `.5`, `1e3` or `2.5E-4`. Anything number-like left over after it, as in
+    // `1.2.3` or `1e+`, makes the whole run malformed.
+    fn lex_number(&mut self, in_fraction: bool) {
+        self.skip_digits();
+        if !in_fraction && self.next_if_char(|ch| ch == '.') {
+            self.skip_digits();
+        }
+        let mut malformed = false;
+        if self.next_if_char(|ch| matches!(ch, 'e' | 'E')) {
+            self.next_if_char(|ch| matches!(ch, '+' | '-'));
+            malformed = !self.skip_digits();
+        }
+        if malformed || matches!(self.chars.peek(), Some(&(_, '.' | '0'..='9'))) {
+            while self.next_if_char(|ch| ch.is_ascii_alphanumeric() || ch == '.') {}
+            self.error(LexErrorKind::InvalidNumber);
+            return;
+        }
+
+        let end = self.chars.peek().map_or(self.input_str.len(), |&(i, _)| i);
+        match TokenType::num_from(&self.input_str[self.token_start..end]) {
+            Some(num) => self.emit(num),
+            None => self.error(LexErrorKind::InvalidNumber),
+        }
+    }
+
+    // Consumes a run of digits, returning whether there was any.
+    fn skip_digits(&mut self) -> bool {
+        let mut any = false;
+        while self.next_if_char(|ch| ch.is_ascii_digit()) {
+            any = true;
+        }
+        any
+    }
+
+    fn next_if_char(&mut self, pred: impl Fn(char) -> bool) -> bool {
+        self.chars.next_if(|&(_, ch)| pred(ch)).is_some()
+    }
+
     fn lex_path(&mut self, curr_idx: usize) {
-        self.capture_start = curr_idx;
-        self.chars.next();
-        for (i, ch) in self.chars.by_ref() {
-            if let Ok(CharType::Whitespace | CharType::Newline) = CharType::try_from(ch) {
+        (self.capture_start, self.capture_end) = (curr_idx, curr_idx);
+        while let Some(&(i, ch)) = self.chars.peek() {
+            if let Ok(
+                CharType::Char
+                | CharType::Digit
+                | CharType::Dot
+                | CharType::ForwSlash
+                | CharType::Minus
+                | CharType::Plus,
+            ) = CharType::try_from(ch)
+            {
+                self.chars.next();
+                self.capture_end = i;
+            } else {
                 break;
             }
-            self.capture_end = i;
         }
         let path = &self.input_str[self.capture_start..=self.capture_end];
-        self.tokens.push(TokenType::Path(path));
+        self.emit(TokenType::Path(path));
     }
 }
 impl<'a> Tokenizer<'a> for Lexer<'a> {
-    fn tokenize(&'a mut self) -> &TokenStream {
+    fn tokenize(&'a mut self) -> Result<&'a TokenStream<'a>, Vec<LexError>> {
         while let Some((i, ch)) = self.chars.next() {
+            self.token_start = i;
             match CharType::try_from(ch) {
-                Ok(ch) => match ch {
+                Ok(char_type) => match char_type {
                     CharType::Char => {
                         (self.capture_start, self.capture_end) = (i, i);
                         while let Some(&(i, ch)) = self.chars.peek() {
-                            if let Ok(CharType::Char | CharType::Digit | CharType::Minus) =
-                                CharType::try_from(ch)
+                            if let Ok(
+                                CharType::Char
+                                | CharType::Digit
+                                | CharType::Minus
+                                | CharType::Squote,
+                            ) = CharType::try_from(ch)
                             {
                                 self.chars.next();
                                 self.capture_end = i;
@@ -61,133 +263,109 @@ impl<'a> Tokenizer<'a> for Lexer<'a> {
                                 break;
                             };
                         }
-                        self.tokens.push(TokenType::ident_or_keyword_from(
+                        self.emit(TokenType::ident_or_keyword_from(
                             &self.input_str[self.capture_start..=self.capture_end],
                         ));
                     }
-                    CharType::Plus => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::Plus) => {
-                                    self.chars.next();
-                                    self.tokens.push(TokenType::Concat)
-                                }
-                                _ => self
-                                    .tokens
-                                    .push(TokenType::AdditiveOperator(AdditiveOperator::Add)),
-                            }
-                        } else {
-                            panic!("Unexpected EOF, after + symbol");
-                        }
-                    }
-                    CharType::Minus => {
-                        if let Some(t) = self.tokens.last() {
-                            match t {
-                                TokenType::Ident(_) | TokenType::Flo(_) | TokenType::Int(_) => self
-                                    .tokens
-                                    .push(TokenType::AdditiveOperator(AdditiveOperator::Sub)),
-                                _ => self.tokens.push(TokenType::ArithmNegation),
-                            }
-                        } else {
-                            panic!("Unexpected EOF before - symbol");
+                    CharType::Plus => match self.peek_char_type() {
+                        Some(CharType::Plus) => {
+                            self.chars.next();
+                            self.emit(TokenType::Concat)
                         }
+                        _ => self.emit(TokenType::AdditiveOperator(AdditiveOperator::Add)),
+                    },
+                    CharType::Minus if matches!(self.chars.peek(), Some(&(_, '>'))) => {
+                        self.chars.next();
+                        self.emit(TokenType::LogImpl);
                     }
-                    CharType::Dquote => {
-                        self.capture_start = i + 1;
-
-                        for (i, ch) in self.chars.by_ref() {
-                            if CharType::is_dquote(ch) {
-                                self.tokens.push(TokenType::StrLiteral(
-                                    &self.input_str[self.capture_start..i],
-                                ));
-                                self.closing_delimiter_found = true;
-                                break;
-                            }
-                        }
-                        if !self.closing_delimiter_found {
-                            panic!("Unexpected EOF, expecting a second, closing double quote");
-                        }
-                        self.closing_delimiter_found = false;
-                    }
+                    // A minus following anything that can end an operand is a
+                    // subtraction, otherwise it negates what comes after it.
+                    CharType::Minus => match self.tokens.last().map(|t| &t.typ) {
+                        Some(
+                            TokenType::Ident(_)
+                            | TokenType::Flo(_)
+                            | TokenType::Int(_)
+                            | TokenType::StrLiteral(_)
+                            | TokenType::IndStrLiteral(_)
+                            | TokenType::StrEnd
+                            | TokenType::Path(_)
+                            | TokenType::NixPath(_)
+                            | TokenType::Bool(_)
+                            | TokenType::Null
+                            | TokenType::CloseParen
+                            | TokenType::CloseSquare
+                            | TokenType::CloseBrace,
+                        ) => self.emit(TokenType::AdditiveOperator(AdditiveOperator::Sub)),
+                        _ => self.emit(TokenType::ArithmNegation),
+                    },
+                    CharType::Dquote => self.lex_str(i, i + 1, false, true),
                     CharType::Squote => {
-                        if let Some((_, next_ch)) = self.chars.next() {
-                            if !CharType::is_squote(next_ch) {
-                                panic!("Expected to find a second single quote");
+                        match self.chars.peek() {
+                            Some(&(_, next_ch)) if CharType::is_squote(next_ch) => {
+                                self.chars.next();
                             }
-                        } else {
-                            panic!("Unexpected EOF, expecting a second single quote");
-                        }
-
-                        self.capture_start = i + 2;
-                        while let Some((i, ch)) = self.chars.next() {
-                            if CharType::is_squote(ch) {
-                                if let Some((_, next_ch)) = self.chars.next() {
-                                    if CharType::is_squote(next_ch) {
-                                        self.tokens.push(TokenType::StrLiteral(
-                                            &self.input_str[self.capture_start..i],
-                                        ));
-                                        self.closing_delimiter_found = true;
-                                        break;
-                                    }
-                                }
+                            _ => {
+                                self.error(LexErrorKind::ExpectedChar('\''));
+                                continue;
                             }
                         }
-                        if !self.closing_delimiter_found {
-                            panic!("Unexpected EOF, expecting a second, closing single quote");
-                        }
-                        self.closing_delimiter_found = false;
+                        self.lex_str(i, i + 2, true, true);
                     }
-                    CharType::Digit => {
-                        (self.capture_start, self.capture_end) = (i, i);
-                        while let Some(&(i, ch)) = self.chars.peek() {
-                            if let Ok(CharType::Digit | CharType::Dot) = CharType::try_from(ch) {
-                                self.chars.next();
-                                self.capture_end = i;
-                            } else {
-                                break;
-                            };
+                    // Braces are counted inside interpolations so that the one
+                    // closing the `${` hands control back to the string.
+                    CharType::OpenBrace => {
+                        if let Some(interpolation) = self.interpolations.last_mut() {
+                            interpolation.depth += 1;
                         }
-                        self.tokens.push(TokenType::num_from(
-                            &self.input_str[self.capture_start..=self.capture_end],
-                        ));
+                        self.emit(TokenType::OpenBrace);
                     }
-                    CharType::Dot => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::ForwSlash) => {
-                                    self.lex_path(i);
-                                }
-                                Err(err) => panic!("{err}"),
-                                _ => {
-                                    self.chars.next();
-                                    self.tokens.push(TokenType::Access);
-                                }
+                    CharType::CloseBrace => {
+                        self.emit(TokenType::CloseBrace);
+                        match self.interpolations.last_mut() {
+                            Some(interpolation) if interpolation.depth == 0 => {
+                                let Interpolation {
+                                    str_start,
+                                    indented,
+                                    ..
+                                } = self.interpolations.pop().unwrap();
+                                self.lex_str(str_start, i + 1, indented, false);
                             }
+                            Some(interpolation) => interpolation.depth -= 1,
+                            None => (),
                         }
                     }
-                    CharType::Tilde => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::ForwSlash) => {
-                                    self.lex_path(i);
-                                }
-                                _ => unimplemented!(),
+                    CharType::Digit => self.lex_number(false),
+                    CharType::Dot => {
+                        let mut lookahead =
+                            self.chars.clone().map(|(_, ch)| CharType::try_from(ch));
+                        match (lookahead.next(), lookahead.next()) {
+                            (Some(Ok(CharType::Digit)), _) => self.lex_number(true),
+                            (Some(Ok(CharType::ForwSlash)), _)
+                            | (Some(Ok(CharType::Dot)), Some(Ok(CharType::ForwSlash))) => {
+                                self.lex_path(i);
                             }
-                        }
-                    }
-                    CharType::ForwSlash => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::Whitespace) => {
-                                    self.chars.next();
-                                    self.tokens.push(TokenType::MultiplicativeOperator(
-                                        MultiplicativeOperator::Div,
-                                    ))
-                                }
-                                _ => self.lex_path(i),
+                            (Some(Ok(CharType::Dot)), Some(Ok(CharType::Dot))) => {
+                                self.chars.next();
+                                self.chars.next();
+                                self.emit(TokenType::Ellipsis);
                             }
+                            _ => self.emit(TokenType::Access),
                         }
                     }
+                    CharType::Tilde => match self.peek_char_type() {
+                        Some(CharType::ForwSlash) => self.lex_path(i),
+                        _ => self.error(LexErrorKind::ExpectedChar('/')),
+                    },
+                    CharType::ForwSlash => match self.peek_char_type() {
+                        Some(CharType::ForwSlash) => {
+                            self.chars.next();
+                            self.emit(TokenType::Update);
+                        }
+                        Some(CharType::Whitespace | CharType::Newline) | None => self.emit(
+                            TokenType::MultiplicativeOperator(MultiplicativeOperator::Div),
+                        ),
+                        _ => self.lex_path(i),
+                    },
                     CharType::Langle => {
                         (self.capture_start, self.capture_end) = (i, i);
                         let iter_clone = self.chars.clone();
@@ -203,114 +381,157 @@ impl<'a> Tokenizer<'a> for Lexer<'a> {
                                     for _ in 0..i - self.capture_start {
                                         self.chars.next();
                                     }
-                                    self.tokens.push(TokenType::NixPath(
+                                    self.emit(TokenType::NixPath(
                                         &self.input_str[self.capture_start..=i],
                                     ));
                                     self.closing_delimiter_found = true;
                                     break;
                                 }
-                                Err(err) => panic!("{err}"),
+                                Err(_) => break,
                                 _ => continue,
                             }
                         }
-                        if !self.closing_delimiter_found {
-                            if let Some((_, next_ch)) = self.chars.peek() {
-                                match CharType::try_from(*next_ch) {
-                                    Ok(CharType::Equals) => {
-                                        self.chars.next();
-                                        self.tokens.push(TokenType::ArithmComparison(
-                                            ArithmComparison::LessOrEquals,
-                                        ));
-                                    }
-                                    Err(err) => panic!("{err}"),
-                                    _ => self
-                                        .tokens
-                                        .push(TokenType::ArithmComparison(ArithmComparison::Less)),
-                                }
-                            }
-                        }
-                    }
-                    CharType::Rangle => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::Equals) => {
+                        if self.closing_delimiter_found {
+                            self.closing_delimiter_found = false;
+                        } else {
+                            match self.peek_char_type() {
+                                Some(CharType::Equals) => {
                                     self.chars.next();
-                                    self.tokens.push(TokenType::ArithmComparison(
-                                        ArithmComparison::MoreOrEquals,
+                                    self.emit(TokenType::ArithmComparison(
+                                        ArithmComparison::LessOrEquals,
                                     ));
                                 }
-                                Err(err) => panic!("{err}"),
-                                _ => self
-                                    .tokens
-                                    .push(TokenType::ArithmComparison(ArithmComparison::More)),
+                                _ => self.emit(TokenType::ArithmComparison(ArithmComparison::Less)),
                             }
                         }
                     }
-                    CharType::Equals => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::Equals) => {
-                                    self.chars.next();
-                                    self.tokens.push(TokenType::LogicalComparison(
-                                        LogicalComparison::CompareEquals,
-                                    ));
-                                }
-                                _ => self.tokens.push(TokenType::Assign),
-                            }
+                    CharType::Ampersand => match self.peek_char_type() {
+                        Some(CharType::Ampersand) => {
+                            self.chars.next();
+                            self.emit(TokenType::And);
                         }
-                    }
-                    CharType::Exclamation => {
-                        if let Some((_, next_ch)) = self.chars.peek() {
-                            match CharType::try_from(*next_ch) {
-                                Ok(CharType::Equals) => {
-                                    self.chars.next();
-                                    self.tokens.push(TokenType::LogicalComparison(
-                                        LogicalComparison::CompareNotEquals,
-                                    ))
-                                }
-                                _ => self.tokens.push(TokenType::LogicalNegation),
+                        _ => self.error(LexErrorKind::InvalidChar(ch)),
+                    },
+                    CharType::Pipe => match self.peek_char_type() {
+                        Some(CharType::Pipe) => {
+                            self.chars.next();
+                            self.emit(TokenType::Or);
+                        }
+                        _ => self.error(LexErrorKind::InvalidChar(ch)),
+                    },
+                    CharType::Rangle => match self.peek_char_type() {
+                        Some(CharType::Equals) => {
+                            self.chars.next();
+                            self.emit(TokenType::ArithmComparison(ArithmComparison::MoreOrEquals));
+                        }
+                        _ => self.emit(TokenType::ArithmComparison(ArithmComparison::More)),
+                    },
+                    CharType::Equals => match self.peek_char_type() {
+                        Some(CharType::Equals) => {
+                            self.chars.next();
+                            self.emit(TokenType::LogicalComparison(
+                                LogicalComparison::CompareEquals,
+                            ));
+                        }
+                        _ => self.emit(TokenType::Assign),
+                    },
+                    CharType::Exclamation => match self.peek_char_type() {
+                        Some(CharType::Equals) => {
+                            self.chars.next();
+                            self.emit(TokenType::LogicalComparison(
+                                LogicalComparison::CompareNotEquals,
+                            ))
+                        }
+                        _ => self.emit(TokenType::LogicalNegation),
+                    },
+                    CharType::Dollar => match self.peek_char_type() {
+                        Some(CharType::OpenBrace) => {
+                            self.chars.next();
+                            if let Some(interpolation) = self.interpolations.last_mut() {
+                                interpolation.depth += 1;
                             }
+                            self.emit(TokenType::Interpolate);
                         }
-                    }
+                        _ => self.error(LexErrorKind::InvalidChar(ch)),
+                    },
                     CharType::Hash => {
                         for (_, ch) in self.chars.by_ref() {
-                            match CharType::try_from(ch) {
-                                Ok(CharType::Newline) => break,
-                                Err(err) => panic!("{err}"),
-                                _ => continue,
+                            if let Ok(CharType::Newline) = CharType::try_from(ch) {
+                                break;
                             }
                         }
                     }
                     CharType::Whitespace | CharType::Newline => {
                         continue;
                     }
-                    _ => {
-                        self.tokens.push(TokenType::from(ch));
-                    }
+                    _ => match TokenType::try_from(char_type) {
+                        Ok(tok) => self.emit(tok),
+                        Err(_) => self.error(LexErrorKind::InvalidChar(ch)),
+                    },
                 },
-                Err(err) => {
-                    panic!("{err}")
-                }
+                Err(_) => self.error(LexErrorKind::InvalidChar(ch)),
             }
         }
 
-        self.tokens.as_slice()
+        // Input ended inside `${`, so the string around it was never closed.
+        if let Some(interpolation) = self.interpolations.pop() {
+            self.token_start = interpolation.str_start;
+            if interpolation.indented {
+                self.error(LexErrorKind::UnterminatedIndString);
+            } else {
+                self.error(LexErrorKind::UnterminatedString);
+            }
+            self.interpolations.clear();
+        }
+
+        if self.errors.is_empty() {
+            Ok(self.tokens.as_slice())
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 }
 
-pub enum TokenStreamErrors {
-    UnexpectedToken(UnexpectedTokenError),
-}
 #[derive(Debug)]
-pub struct UnexpectedTokenError {
-    msg: String,
+struct Interpolation {
+    str_start: usize,
+    indented: bool,
+    // Braces opened inside the interpolation and not closed yet.
+    depth: usize,
 }
-impl UnexpectedTokenError {
-    fn new(exp_tok: &TokenType, got_tok: &TokenType) -> Self {
-        UnexpectedTokenError {
-            msg: format!("Expected token: {:?}, but got: {:?}", exp_tok, got_tok),
+
+#[derive(Debug, PartialEq)]
+pub enum LexErrorKind {
+    InvalidChar(char),
+    ExpectedChar(char),
+    UnterminatedString,
+    UnterminatedIndString,
+    InvalidNumber,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+impl LexError {
+    pub fn new(kind: LexErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            LexErrorKind::InvalidChar(ch) => write!(f, "invalid character '{}'", ch)?,
+            LexErrorKind::ExpectedChar(ch) => write!(f, "expected '{}'", ch)?,
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal")?,
+            LexErrorKind::UnterminatedIndString => {
+                write!(f, "unterminated indented string literal")?
+            }
+            LexErrorKind::InvalidNumber => write!(f, "malformed number literal")?,
         }
+        write!(f, " at {}", self.span)
     }
 }
 
-pub type TokenStream<'a> = [TokenType<'a>];
+pub type TokenStream<'a> = [Token<'a>];
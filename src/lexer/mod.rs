@@ -2,6 +2,7 @@
 mod lexer;
 
 mod chars;
+pub mod span;
 mod tests_lexer;
 pub mod tokens;
 
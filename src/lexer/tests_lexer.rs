@@ -1,9 +1,14 @@
 #[cfg(test)]
 mod tests {
     use crate::lexer::chars::*;
+    use crate::lexer::span::Span;
     use crate::lexer::tokens::*;
     use crate::lexer::*;
 
+    fn token_types<'a>(toks: &TokenStream<'a>) -> Vec<TokenType<'a>> {
+        toks.iter().map(|tok| tok.typ.clone()).collect()
+    }
+
     #[test]
     fn token_try_from_char() {
         let test_cases: Vec<(char, CharType)> = vec![
@@ -68,6 +73,14 @@ mod tests {
                     TokenType::Int(1),
                 ],
             ),
+            (
+                "foldl' map x''",
+                vec![
+                    TokenType::Ident("foldl'"),
+                    TokenType::Ident("map"),
+                    TokenType::Ident("x''"),
+                ],
+            ),
             (
                 "xyz = 10;",
                 vec![
@@ -107,7 +120,7 @@ mod tests {
                     I can't not use ', so sad
                 '';",
                 vec![
-                    TokenType::StrLiteral(
+                    TokenType::IndStrLiteral(
                         "
                     I can't not use ', so sad
                 ",
@@ -115,6 +128,13 @@ mod tests {
                     TokenType::Semicolon,
                 ],
             ),
+            (
+                r#""a \"b\" \${c} $${d}" ''e ''${f} ''' ''\n''"#,
+                vec![
+                    TokenType::StrLiteral(r#"a \"b\" \${c} $${d}"#),
+                    TokenType::IndStrLiteral(r"e ''${f} ''' ''\n"),
+                ],
+            ),
             (
                 "bashScript = ''
                     #!/usr/bin/env bash
@@ -132,7 +152,7 @@ mod tests {
                 vec![
                     TokenType::Ident("bashScript"),
                     TokenType::Assign,
-                    TokenType::StrLiteral(
+                    TokenType::IndStrLiteral(
                         "
                     #!/usr/bin/env bash
                     # This is a simple Bash script that greets the user
@@ -154,8 +174,70 @@ mod tests {
 
         for (input, want) in test_cases {
             let mut lexer = Lexer::new(input);
-            let got = lexer.tokenize();
-            assert_eq!(*got, want);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
+        }
+    }
+
+    #[test]
+    fn tokenize_interpolated_str() {
+        let test_cases: Vec<(&str, Vec<TokenType>)> = vec![
+            (
+                "\"Hello, ${name}!\"",
+                vec![
+                    TokenType::StrStart,
+                    TokenType::StrPart("Hello, "),
+                    TokenType::Interpolate,
+                    TokenType::Ident("name"),
+                    TokenType::CloseBrace,
+                    TokenType::StrPart("!"),
+                    TokenType::StrEnd,
+                ],
+            ),
+            (
+                "''${a}${ { b = 1; }.b }''",
+                vec![
+                    TokenType::IndStrStart,
+                    TokenType::Interpolate,
+                    TokenType::Ident("a"),
+                    TokenType::CloseBrace,
+                    TokenType::Interpolate,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::CloseBrace,
+                    TokenType::StrEnd,
+                ],
+            ),
+            (
+                "\"a${\"b${c}\"}\" + \"$\"",
+                vec![
+                    TokenType::StrStart,
+                    TokenType::StrPart("a"),
+                    TokenType::Interpolate,
+                    TokenType::StrStart,
+                    TokenType::StrPart("b"),
+                    TokenType::Interpolate,
+                    TokenType::Ident("c"),
+                    TokenType::CloseBrace,
+                    TokenType::StrEnd,
+                    TokenType::CloseBrace,
+                    TokenType::StrEnd,
+                    TokenType::AdditiveOperator(AdditiveOperator::Add),
+                    TokenType::StrLiteral("$"),
+                ],
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
         }
     }
 
@@ -199,14 +281,27 @@ mod tests {
 
         for (input, want) in test_cases {
             let mut lexer = Lexer::new(input);
-            let got = lexer.tokenize();
-            assert_eq!(*got, want);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
         }
     }
 
     #[test]
     fn tokenize_operators() {
         let test_cases: Vec<(&str, Vec<TokenType>)> = vec![
+            (
+                "a&&b||c -> -d",
+                vec![
+                    TokenType::Ident("a"),
+                    TokenType::And,
+                    TokenType::Ident("b"),
+                    TokenType::Or,
+                    TokenType::Ident("c"),
+                    TokenType::LogImpl,
+                    TokenType::ArithmNegation,
+                    TokenType::Ident("d"),
+                ],
+            ),
             (
                 "5 <1.1",
                 vec![
@@ -239,6 +334,38 @@ mod tests {
                     TokenType::Flo(1.1),
                 ],
             ),
+            (
+                "a//{ }",
+                vec![
+                    TokenType::Ident("a"),
+                    TokenType::Update,
+                    TokenType::OpenBrace,
+                    TokenType::CloseBrace,
+                ],
+            ),
+            (
+                "(a) -1 * -b",
+                vec![
+                    TokenType::OpenParen,
+                    TokenType::Ident("a"),
+                    TokenType::CloseParen,
+                    TokenType::AdditiveOperator(AdditiveOperator::Sub),
+                    TokenType::Int(1),
+                    TokenType::MultiplicativeOperator(MultiplicativeOperator::Mult),
+                    TokenType::ArithmNegation,
+                    TokenType::Ident("b"),
+                ],
+            ),
+            (
+                "a.${b}",
+                vec![
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Interpolate,
+                    TokenType::Ident("b"),
+                    TokenType::CloseBrace,
+                ],
+            ),
             (
                 "war!=peace",
                 vec![
@@ -283,44 +410,296 @@ mod tests {
 
         for (input, want) in test_cases {
             let mut lexer = Lexer::new(input);
-            let got = lexer.tokenize();
-            assert_eq!(*got, want);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
+        }
+    }
+
+    #[test]
+    fn tokenize_lambda() {
+        let test_cases: Vec<(&str, Vec<TokenType>)> = vec![
+            (
+                "x: x",
+                vec![
+                    TokenType::Ident("x"),
+                    TokenType::Colon,
+                    TokenType::Ident("x"),
+                ],
+            ),
+            (
+                "args@{ a, b ? 2, ... }: a",
+                vec![
+                    TokenType::Ident("args"),
+                    TokenType::At,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Comma,
+                    TokenType::Ident("b"),
+                    TokenType::Has,
+                    TokenType::Int(2),
+                    TokenType::Comma,
+                    TokenType::Ellipsis,
+                    TokenType::CloseBrace,
+                    TokenType::Colon,
+                    TokenType::Ident("a"),
+                ],
+            ),
+            (
+                "set.attr",
+                vec![
+                    TokenType::Ident("set"),
+                    TokenType::Access,
+                    TokenType::Ident("attr"),
+                ],
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
+        }
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        let test_cases: Vec<(&str, Vec<Span>)> = vec![
+            (
+                "x = 10;",
+                vec![
+                    Span::new(0, 1, 1, 1),
+                    Span::new(2, 3, 1, 3),
+                    Span::new(4, 6, 1, 5),
+                    Span::new(6, 7, 1, 7),
+                ],
+            ),
+            (
+                "let\n  s = \"héllo\"; # comment\nin s == ./a/b",
+                vec![
+                    Span::new(0, 3, 1, 1),
+                    Span::new(6, 7, 2, 3),
+                    Span::new(8, 9, 2, 5),
+                    Span::new(10, 18, 2, 7),
+                    Span::new(18, 19, 2, 14),
+                    Span::new(30, 32, 3, 1),
+                    Span::new(33, 34, 3, 4),
+                    Span::new(35, 37, 3, 6),
+                    Span::new(38, 43, 3, 9),
+                ],
+            ),
+            (
+                "\"é${x}é\" + y",
+                vec![
+                    Span::new(0, 1, 1, 1),
+                    Span::new(1, 3, 1, 2),
+                    Span::new(3, 5, 1, 3),
+                    Span::new(5, 6, 1, 5),
+                    Span::new(6, 7, 1, 6),
+                    Span::new(7, 9, 1, 7),
+                    Span::new(9, 10, 1, 8),
+                    Span::new(11, 12, 1, 10),
+                    Span::new(13, 14, 1, 12),
+                ],
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let got: Vec<Span> = lexer
+                .tokenize()
+                .unwrap()
+                .iter()
+                .map(|tok| tok.span)
+                .collect();
+            assert_eq!(got, want);
         }
     }
 
     #[test]
-    #[should_panic = "Unexpected EOF, expecting a second, closing single quote"]
     fn try_tokenize_no_closing_squote() {
-        let test_cases: Vec<&str> = vec![
+        let test_cases: Vec<(&str, Vec<LexError>)> = vec![(
             "bashScript = ''
                     #!/usr/bin/env bash
-                    # This is a simple Bash script that greets the user
+                    echo \"Hello, $name! Welcome to the Bash scripting world.\"
+                ;",
+            vec![LexError::new(
+                LexErrorKind::UnterminatedIndString,
+                Span::new(13, 151, 1, 14),
+            )],
+        )];
 
-                    # Prompt the user for their name
-                    echo \"What's your name?\"
-                    read name
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(lexer.tokenize().unwrap_err(), want);
+        }
+    }
 
-                    # Print a greeting message
-                    echo \"Hello, $name! Welcome to the Bash scripting world.\"
+    #[test]
+    fn try_tokenize_no_closing_dquote() {
+        let test_cases: Vec<(&str, Vec<LexError>)> = vec![
+            (
+                "\"Oops forgot to close this one!",
+                vec![LexError::new(
+                    LexErrorKind::UnterminatedString,
+                    Span::new(0, 31, 1, 1),
+                )],
+            ),
+            (
+                "weirdness = \"",
+                vec![LexError::new(
+                    LexErrorKind::UnterminatedString,
+                    Span::new(12, 13, 1, 13),
+                )],
+            ),
+            (
+                "\"a ${ b",
+                vec![LexError::new(
+                    LexErrorKind::UnterminatedString,
+                    Span::new(0, 7, 1, 1),
+                )],
+            ),
+            (
+                "\"a ${ b }",
+                vec![LexError::new(
+                    LexErrorKind::UnterminatedString,
+                    Span::new(0, 9, 1, 1),
+                )],
+            ),
+        ];
 
-                    # End of the script
-                ;",
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(lexer.tokenize().unwrap_err(), want);
+        }
+    }
+
+    #[test]
+    fn try_tokenize_collects_all_errors() {
+        let test_cases: Vec<(&str, Vec<LexError>)> = vec![
+            (
+                "a = 1 ₴ 2;\nb = $;",
+                vec![
+                    LexError::new(LexErrorKind::InvalidChar('₴'), Span::new(6, 9, 1, 7)),
+                    LexError::new(LexErrorKind::InvalidChar('$'), Span::new(17, 18, 2, 5)),
+                ],
+            ),
+            (
+                "home = ~; q = 'a'",
+                vec![
+                    LexError::new(LexErrorKind::ExpectedChar('/'), Span::new(7, 8, 1, 8)),
+                    LexError::new(LexErrorKind::ExpectedChar('\''), Span::new(14, 15, 1, 15)),
+                ],
+            ),
+            (
+                "a & b | c",
+                vec![
+                    LexError::new(LexErrorKind::InvalidChar('&'), Span::new(2, 3, 1, 3)),
+                    LexError::new(LexErrorKind::InvalidChar('|'), Span::new(6, 7, 1, 7)),
+                ],
+            ),
+            (
+                "v = 1.2.3",
+                vec![LexError::new(
+                    LexErrorKind::InvalidNumber,
+                    Span::new(4, 9, 1, 5),
+                )],
+            ),
+            (
+                "[ 1e+ 2.5E 1.2e3.4 ]",
+                vec![
+                    LexError::new(LexErrorKind::InvalidNumber, Span::new(2, 5, 1, 3)),
+                    LexError::new(LexErrorKind::InvalidNumber, Span::new(6, 10, 1, 7)),
+                    LexError::new(LexErrorKind::InvalidNumber, Span::new(11, 18, 1, 12)),
+                ],
+            ),
+            (
+                "9223372036854775808",
+                vec![LexError::new(
+                    LexErrorKind::InvalidNumber,
+                    Span::new(0, 19, 1, 1),
+                )],
+            ),
         ];
 
-        for input in test_cases {
+        for (input, want) in test_cases {
             let mut lexer = Lexer::new(input);
-            let _ = lexer.tokenize();
+            assert_eq!(lexer.tokenize().unwrap_err(), want);
         }
     }
 
     #[test]
-    #[should_panic = "Unexpected EOF, expecting a second, closing double quote"]
-    fn try_tokenize_no_closing_dquote() {
-        let test_cases: Vec<&str> = vec!["\"Oops forgot to close this one!", "weirdness = \""];
+    fn tokenize_numbers() {
+        let test_cases: Vec<(&str, Vec<TokenType>)> = vec![
+            ("0 007", vec![TokenType::Int(0), TokenType::Int(7)]),
+            (
+                "1.5 1. .5 0.25",
+                vec![
+                    TokenType::Flo(1.5),
+                    TokenType::Flo(1.0),
+                    TokenType::Flo(0.5),
+                    TokenType::Flo(0.25),
+                ],
+            ),
+            (
+                "1e3 2.5E-4 .5e+2 3E0",
+                vec![
+                    TokenType::Flo(1000.0),
+                    TokenType::Flo(0.00025),
+                    TokenType::Flo(50.0),
+                    TokenType::Flo(3.0),
+                ],
+            ),
+            (
+                "1e3-2",
+                vec![
+                    TokenType::Flo(1000.0),
+                    TokenType::AdditiveOperator(AdditiveOperator::Sub),
+                    TokenType::Int(2),
+                ],
+            ),
+            (
+                "a.b ...",
+                vec![
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::Ellipsis,
+                ],
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want, "{}", input);
+        }
+    }
 
-        for input in test_cases {
+    #[test]
+    fn tokenize_edge_of_input() {
+        let test_cases: Vec<(&str, Vec<TokenType>)> = vec![
+            (
+                "-1 +",
+                vec![
+                    TokenType::ArithmNegation,
+                    TokenType::Int(1),
+                    TokenType::AdditiveOperator(AdditiveOperator::Add),
+                ],
+            ),
+            (
+                "9223372036854775807 0.1",
+                vec![TokenType::Int(i64::MAX), TokenType::Flo(0.1)],
+            ),
+            (
+                "x =\t# trailing comment with ünicode",
+                vec![TokenType::Ident("x"), TokenType::Assign],
+            ),
+        ];
+
+        for (input, want) in test_cases {
             let mut lexer = Lexer::new(input);
-            let _ = lexer.tokenize();
+            let got = lexer.tokenize().unwrap();
+            assert_eq!(token_types(got), want);
         }
     }
 }
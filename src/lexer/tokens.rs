@@ -1,13 +1,33 @@
 use crate::lexer::chars::CharType;
+use crate::lexer::span::Span;
 
 #[derive(Debug, PartialEq)]
+pub struct Token<'a> {
+    pub typ: TokenType<'a>,
+    pub span: Span,
+}
+impl<'a> Token<'a> {
+    pub fn new(typ: TokenType<'a>, span: Span) -> Self {
+        Self { typ, span }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum TokenType<'a> {
     Ident(&'a str),
+    /// Contents of a string as written, with escape sequences still in it.
     StrLiteral(&'a str),
+    IndStrLiteral(&'a str),
+    /// Opens a string containing `${...}`; it is made of `StrPart`s and
+    /// interpolations up to the matching `StrEnd`.
+    StrStart,
+    IndStrStart,
+    StrPart(&'a str),
+    StrEnd,
     Path(&'a str),
     NixPath(&'a str),
-    Int(i32),
-    Flo(f32),
+    Int(i64),
+    Flo(f64),
     LogicalComparison(LogicalComparison),
     ArithmComparison(ArithmComparison),
     AdditiveOperator(AdditiveOperator),
@@ -17,14 +37,18 @@ pub enum TokenType<'a> {
     ArithmNegation,
     Assign,
     Semicolon,
+    Colon,
+    Comma,
+    At,
+    Ellipsis,
     Let,
     In,
     Inherit,
+    Assert,
     Import,
     With,
     And,
     Or,
-    Map,
     Null,
     CloseBrace,
     OpenBrace,
@@ -33,6 +57,7 @@ pub enum TokenType<'a> {
     OpenParen,
     CloseParen,
     Access,
+    Interpolate,
     If,
     Then,
     Else,
@@ -43,13 +68,13 @@ pub enum TokenType<'a> {
     Has,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum LogicalComparison {
     CompareEquals,
     CompareNotEquals,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum ArithmComparison {
     More,
     Less,
@@ -57,23 +82,28 @@ pub enum ArithmComparison {
     LessOrEquals,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum AdditiveOperator {
     Add,
     Sub,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum MultiplicativeOperator {
     Mult,
     Div,
 }
 
-impl<'a> From<CharType> for TokenType<'a> {
-    fn from(input_char_type: CharType) -> Self {
-        match input_char_type {
+impl<'a> TryFrom<CharType> for TokenType<'a> {
+    type Error = CharType;
+
+    fn try_from(input_char_type: CharType) -> Result<Self, Self::Error> {
+        let tok = match input_char_type {
             CharType::Equals => Self::Assign,
             CharType::Semicolon => Self::Semicolon,
+            CharType::Colon => Self::Colon,
+            CharType::Comma => Self::Comma,
+            CharType::At => Self::At,
             CharType::Asterisk => Self::MultiplicativeOperator(MultiplicativeOperator::Mult),
             CharType::OpenBrace => Self::OpenBrace,
             CharType::CloseBrace => Self::CloseBrace,
@@ -82,8 +112,9 @@ impl<'a> From<CharType> for TokenType<'a> {
             CharType::Question => Self::Has,
             CharType::OpenParen => Self::OpenParen,
             CharType::CloseParen => Self::CloseParen,
-            _ => panic!("unhandled simple type provided: {:?}", input_char_type),
-        }
+            _ => return Err(input_char_type),
+        };
+        Ok(tok)
     }
 }
 
@@ -95,7 +126,7 @@ impl<'a> TokenType<'a> {
             IMPORT => Self::Import,
             WITH => Self::With,
             INHERIT => Self::Inherit,
-            MAP => Self::Map,
+            ASSERT => Self::Assert,
             NULL => Self::Null,
             TRUE | FALSE => Self::bool_from(chars),
             IF => Self::If,
@@ -112,14 +143,13 @@ impl<'a> TokenType<'a> {
         }
     }
 
-    pub fn num_from(chars: &str) -> Self {
-        if let Ok(parsed_int) = chars.parse::<i32>() {
-            return TokenType::Int(parsed_int);
-        }
-        if let Ok(parsed_flo) = chars.parse::<f32>() {
-            return TokenType::Flo(parsed_flo);
+    /// Integer literals that don't fit in an `i64` are rejected rather than
+    /// read as floats.
+    pub fn num_from(chars: &str) -> Option<Self> {
+        if chars.bytes().all(|b| b.is_ascii_digit()) {
+            return chars.parse::<i64>().ok().map(TokenType::Int);
         }
-        panic!("failed to parse a number chars: ${chars} to token type");
+        chars.parse::<f64>().ok().map(TokenType::Flo)
     }
     pub fn bool_from(chars: &str) -> Self {
         if let Ok(b) = chars.parse::<bool>() {
@@ -132,14 +162,10 @@ impl<'a> TokenType<'a> {
 const LET: &str = "let";
 const IN: &str = "in";
 const INHERIT: &str = "inherit";
+const ASSERT: &str = "assert";
 const IMPORT: &str = "import";
 const WITH: &str = "with";
-const MAP: &str = "map";
 const NULL: &str = "null";
-const RELATIVE_PATH: &str = "./";
-const ABSOLUTE_PATH: &str = "/";
-const HOME_PATH: &str = "~";
-const NIX_PATH: &str = "<";
 const IF: &str = "if";
 const THEN: &str = "then";
 const ELSE: &str = "else";
@@ -1,14 +1,15 @@
-mod lexer;
-mod parser;
-mod runtime;
+pub mod lexer;
+pub mod parser;
+pub mod runtime;
+mod stack;
 
 #[derive(Debug)]
-struct Ast<'a> {
-    root: &'a str,
+pub struct Ast<'a> {
+    pub root: &'a str,
 }
-struct AstGiver;
+pub struct AstGiver;
 impl<'a> AstGiver {
-    fn give_ast() -> Ast<'a> {
+    pub fn give_ast() -> Ast<'a> {
         Ast { root: "xx" }
     }
 }
@@ -1,5 +1,100 @@
 mod cli;
 
-fn main() {
-    unimplemented!();
+use std::process::ExitCode;
+
+use cli::repl::Repl;
+use cli::{Cli, Command, USAGE};
+use nix_interpreter_lib::lexer::{Lexer, Tokenizer};
+use nix_interpreter_lib::parser::{AstParser, Parser};
+use nix_interpreter_lib::runtime::Interpreter;
+
+// Exit codes: 1 when the input fails to lex, parse or evaluate, 2 when the
+// command line itself is wrong or the input can't be read.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+fn main() -> ExitCode {
+    let cli = match Cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    let input = match (&cli.command, &cli.input) {
+        (Command::Repl, _) => {
+            return match Repl::new().run(std::io::stdin().lock(), std::io::stdout()) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    eprintln!("error: {}", err);
+                    ExitCode::from(EXIT_FAILURE)
+                }
+            }
+        }
+        (_, Some(input)) => input,
+        (_, None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+    };
+    let source = match input.read() {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: failed to read input: {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    run(&cli.command, &source)
+}
+
+fn run(command: &Command, source: &str) -> ExitCode {
+    let mut lexer = Lexer::new(source);
+    let toks = match lexer.tokenize() {
+        Ok(toks) => toks,
+        Err(errors) => {
+            for err in errors {
+                eprintln!("error: {}", err);
+            }
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+
+    if let Command::Tokenize = command {
+        for tok in toks {
+            println!("{}\t{:?}", tok.span, tok.typ);
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let ast = match AstParser::new(toks).with_source(source).parse() {
+        Ok(ast) => ast,
+        Err(parse_errors) => {
+            for err in parse_errors.errors {
+                eprintln!("error: {}", err);
+            }
+            return ExitCode::from(EXIT_FAILURE);
+        }
+    };
+
+    if let Command::Parse = command {
+        println!("{:#?}", ast);
+        return ExitCode::SUCCESS;
+    }
+
+    let mut interpreter = Interpreter::new(&ast);
+    match interpreter
+        .interpret()
+        .and_then(|value| interpreter.force_deep(value))
+    {
+        Ok(value) => {
+            println!("{}", value);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
 }
@@ -1,257 +1,308 @@
+use crate::lexer::span::Span;
+use crate::stack::grow_stack;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::rc::Rc;
 
 pub trait AstNode<'a> {
     fn get_literal(&self) -> &'a str;
 }
 
-#[derive(PartialEq)]
-pub enum Expr<'a> {
-    Unary(Box<UnaryExpr<'a>>),
-    Binary(Box<BinaryExpr<'a>>),
-    Binding(Box<BindingExpr<'a>>),
-    Ident(IdentExpr<'a>),
-    Literal(LiteralExpr<'a>),
-    Set(SetExpr<'a>),
-    List(ListExpr<'a>),
-    Let(Box<LetExpr<'a>>),
-    With(Box<WithExpr<'a>>),
-    If(Box<IfExpr<'a>>),
-    Select(Box<SelectExpr<'a>>),
-    Apply(Box<ApplyExpr<'a>>),
+#[derive(PartialEq, Clone)]
+pub struct Expr {
+    pub kind: Rc<ExprKind>,
+    pub span: Span,
 }
-impl<'a> Expr<'a> {
-    pub fn new_str(s: &'a str) -> Self {
-        Expr::Literal(LiteralExpr::Str(s))
+
+#[derive(PartialEq, Clone)]
+pub enum ExprKind {
+    Unary(Box<UnaryExpr>),
+    Binary(Box<BinaryExpr>),
+    Binding(Box<BindingExpr>),
+    Ident(IdentExpr),
+    /// A binding written as `inherit name;`, looked up in the scope
+    /// enclosing the set or let block rather than in the block itself.
+    Inherit(IdentExpr),
+    Literal(LiteralExpr),
+    Interpolated(InterpolatedExpr),
+    Set(SetExpr),
+    List(ListExpr),
+    Let(Box<LetExpr>),
+    With(Box<WithExpr>),
+    If(Box<IfExpr>),
+    Assert(Box<AssertExpr>),
+    Select(Box<SelectExpr>),
+    HasAttr(Box<HasAttrExpr>),
+    Apply(Box<ApplyExpr>),
+    Lambda(Rc<LambdaExpr>),
+}
+impl Expr {
+    pub fn new(kind: ExprKind) -> Self {
+        Self {
+            kind: Rc::new(kind),
+            span: Span::default(),
+        }
+    }
+    pub fn spanned(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    pub fn new_str(s: &str) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::Str(Rc::from(s))))
     }
-    pub fn new_path(s: &'a str) -> Self {
-        Expr::Literal(LiteralExpr::Path(s))
+    pub fn new_path(s: &str) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::Path(Rc::from(s))))
     }
-    pub fn new_nix_path(s: &'a str) -> Self {
-        Expr::Literal(LiteralExpr::NixPath(s))
+    pub fn new_nix_path(s: &str) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::NixPath(Rc::from(s))))
     }
-    pub fn new_int(i: i32) -> Self {
-        Expr::Literal(LiteralExpr::Int(i))
+    pub fn new_int(i: i64) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::Int(i)))
     }
-    pub fn new_flo(f: f32) -> Self {
-        Expr::Literal(LiteralExpr::Flo(f))
+    pub fn new_flo(f: f64) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::Flo(f)))
+    }
+    pub fn new_bool(b: bool) -> Self {
+        Expr::new(ExprKind::Literal(LiteralExpr::Bool(b)))
     }
     pub fn new_null() -> Self {
-        Expr::Literal(LiteralExpr::Null())
+        Expr::new(ExprKind::Literal(LiteralExpr::Null()))
+    }
+    pub fn new_interpolated(parts: Vec<StrPart>) -> Self {
+        Expr::new(ExprKind::Interpolated(InterpolatedExpr::new(parts)))
     }
 
-    pub fn new_add(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Add(),
-        )))
+    fn new_binary(left: Expr, right: Expr, typ: BinaryExprType) -> Self {
+        let span = left.span.to(right.span);
+        Expr::new(ExprKind::Binary(Box::new(BinaryExpr::new(
+            left, right, typ,
+        ))))
+        .spanned(span)
     }
-    pub fn new_sub(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Sub(),
-        )))
+    pub fn new_add(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Add())
     }
-    pub fn new_mult(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Mult(),
-        )))
+    pub fn new_sub(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Sub())
     }
-    pub fn new_div(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Div(),
-        )))
+    pub fn new_mult(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Mult())
+    }
+    pub fn new_div(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Div())
     }
 
-    pub fn new_compare_equals(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Equals(),
-        )))
+    pub fn new_compare_equals(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Equals())
     }
-    pub fn new_compare_not_equals(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::NotEquals(),
-        )))
+    pub fn new_compare_not_equals(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::NotEquals())
     }
-    pub fn new_compare_more(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::More(),
-        )))
+    pub fn new_compare_more(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::More())
     }
-    pub fn new_compare_less(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Less(),
-        )))
+    pub fn new_compare_less(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Less())
     }
-    pub fn new_compare_more_or_equals(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::MoreOrEquals(),
-        )))
+    pub fn new_compare_more_or_equals(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::MoreOrEquals())
     }
-    pub fn new_compare_less_or_equals(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::LessOrEquals(),
-        )))
+    pub fn new_compare_less_or_equals(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::LessOrEquals())
     }
 
-    pub fn new_concat(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Concat(),
-        )))
-    }
-    pub fn new_and(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::And(),
-        )))
+    pub fn new_concat(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Concat())
     }
-    pub fn new_or(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(left, right, BinaryExprType::Or())))
+    pub fn new_and(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::And())
     }
-    pub fn new_logical_disjunction(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Arrow(),
-        )))
+    pub fn new_or(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Or())
     }
-    pub fn new_has(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Has(),
-        )))
+    pub fn new_implication(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Arrow())
     }
-    pub fn new_update(left: Expr<'a>, right: Expr<'a>) -> Self {
-        Expr::Binary(Box::new(BinaryExpr::new(
-            left,
-            right,
-            BinaryExprType::Update(),
-        )))
+    pub fn new_update(left: Expr, right: Expr) -> Self {
+        Expr::new_binary(left, right, BinaryExprType::Update())
     }
 
-    pub fn new_logical_negation(right: Expr<'a>) -> Self {
-        Expr::Unary(Box::new(UnaryExpr::new(
+    pub fn new_logical_negation(right: Expr) -> Self {
+        let span = right.span;
+        Expr::new(ExprKind::Unary(Box::new(UnaryExpr::new(
             right,
             UnaryExprType::LogicalNegation(),
-        )))
+        ))))
+        .spanned(span)
     }
-    pub fn new_arithmetic_negation(right: Expr<'a>) -> Self {
-        Expr::Unary(Box::new(UnaryExpr::new(
+    pub fn new_arithmetic_negation(right: Expr) -> Self {
+        let span = right.span;
+        Expr::new(ExprKind::Unary(Box::new(UnaryExpr::new(
             right,
             UnaryExprType::ArithmNegation(),
-        )))
+        ))))
+        .spanned(span)
     }
 
-    pub fn new_ident(name: &'a str) -> Self {
-        Expr::Ident(IdentExpr::new(name))
+    pub fn new_ident(name: &str) -> Self {
+        Expr::new(ExprKind::Ident(IdentExpr::new(name)))
+    }
+    pub fn new_inherit(name: &str) -> Self {
+        Expr::new(ExprKind::Inherit(IdentExpr::new(name)))
     }
-    pub fn new_binding(ident: IdentExpr<'a>, expr: Expr<'a>) -> Self {
-        Expr::Binding(Box::new(BindingExpr::new(ident, expr)))
+    pub fn new_binding(ident: IdentExpr, expr: Expr) -> Self {
+        Expr::new(ExprKind::Binding(Box::new(BindingExpr::new(ident, expr))))
     }
 
-    pub fn new_set(elems: BTreeMap<&'a str, Expr<'a>>) -> Self {
-        Expr::Set(SetExpr::new(elems))
+    pub fn new_set(elems: BTreeMap<&str, Expr>) -> Self {
+        Expr::new(ExprKind::Set(SetExpr::from_borrowed(elems, false)))
+    }
+    pub fn new_rec_set(elems: BTreeMap<&str, Expr>) -> Self {
+        Expr::new(ExprKind::Set(SetExpr::from_borrowed(elems, true)))
     }
-    pub fn new_list(elems: Vec<Expr<'a>>) -> Self {
-        Expr::List(ListExpr::new(elems))
+    pub fn new_list(elems: Vec<Expr>) -> Self {
+        Expr::new(ExprKind::List(ListExpr::new(elems)))
     }
 
-    pub fn new_let(bindings: BTreeMap<IdentExpr<'a>, Expr<'a>>, body: Expr<'a>) -> Self {
-        Expr::Let(Box::new(LetExpr::new(bindings, body)))
+    pub fn new_let(bindings: BTreeMap<IdentExpr, Expr>, body: Expr) -> Self {
+        Expr::new(ExprKind::Let(Box::new(LetExpr::new(bindings, body))))
     }
-    pub fn new_with(scope: Expr<'a>, expr: Expr<'a>) -> Self {
-        Expr::With(Box::new(WithExpr::new(scope, expr)))
+    pub fn new_with(scope: Expr, expr: Expr) -> Self {
+        Expr::new(ExprKind::With(Box::new(WithExpr::new(scope, expr))))
     }
-    pub fn new_if(cond: Expr<'a>, truthy: Expr<'a>, falsy: Expr<'a>) -> Self {
-        Expr::If(Box::new(IfExpr::new(cond, truthy, falsy)))
+    pub fn new_if(cond: Expr, truthy: Expr, falsy: Expr) -> Self {
+        Expr::new(ExprKind::If(Box::new(IfExpr::new(cond, truthy, falsy))))
     }
-    pub fn new_select(set: Expr<'a>, field: IdentExpr<'a>) -> Self {
-        Expr::Select(Box::new(SelectExpr::new(set, field)))
+    pub fn new_assert(cond: Expr, body: Expr) -> Self {
+        Expr::new(ExprKind::Assert(Box::new(AssertExpr::new(cond, body))))
     }
-    pub fn new_apply(func: Expr<'a>, arg: Expr<'a>) -> Self {
-        Expr::Apply(Box::new(ApplyExpr::new(func, arg)))
+    pub fn new_select(set: Expr, path: Vec<AttrName>, default: Option<Expr>) -> Self {
+        let span = set.span;
+        Expr::new(ExprKind::Select(Box::new(SelectExpr::new(
+            set, path, default,
+        ))))
+        .spanned(span)
+    }
+    pub fn new_has(set: Expr, path: Vec<AttrName>) -> Self {
+        let span = set.span;
+        Expr::new(ExprKind::HasAttr(Box::new(HasAttrExpr::new(set, path)))).spanned(span)
+    }
+    pub fn new_apply(func: Expr, arg: Expr) -> Self {
+        let span = func.span.to(arg.span);
+        Expr::new(ExprKind::Apply(Box::new(ApplyExpr::new(func, arg)))).spanned(span)
+    }
+    pub fn new_lambda(formals: Formals, body: Expr) -> Self {
+        Expr::new(ExprKind::Lambda(Rc::new(LambdaExpr::new(formals, body))))
     }
 }
-impl<'a> fmt::Debug for Expr<'a> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Binary(op) => write!(f, "{:?}", op),
-            Expr::Literal(lit) => write!(f, "{:?}", lit),
-            Expr::Ident(name) => write!(f, "{:?}", name),
-            Expr::Let(val) => write!(
-                f,
-                "\nlet bindings: \n{:?}\n let body: {:?}",
-                val.bindings, val.body
-            ),
-            Expr::With(val) => write!(f, "{:?}", val),
-            Expr::List(val) => write!(f, "{:?}", val),
-            Expr::Set(val) => write!(f, "{:?}", val),
-            _ => write!(f, "unhandled"),
+// Dropping the last reference to a deeply nested tree recurses once per
+// level, so the kind is dropped with the stack grown as needed.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        if let Some(kind) = Rc::get_mut(&mut self.kind) {
+            let kind = std::mem::replace(kind, ExprKind::Literal(LiteralExpr::Null()));
+            grow_stack(move || drop(kind));
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct SetExpr<'a> {
-    elems: std::collections::BTreeMap<&'a str, Expr<'a>>,
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        grow_stack(|| self.fmt_kind(f))
+    }
+}
+impl Expr {
+    fn fmt_kind(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.kind {
+            ExprKind::Unary(op) => fmt::Debug::fmt(op, f),
+            ExprKind::Binary(op) => fmt::Debug::fmt(op, f),
+            ExprKind::Binding(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Literal(lit) => fmt::Debug::fmt(lit, f),
+            ExprKind::Interpolated(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Ident(name) => fmt::Debug::fmt(name, f),
+            ExprKind::Inherit(name) => write!(f, "Inherit({:?})", name),
+            ExprKind::Let(val) => fmt::Debug::fmt(val, f),
+            ExprKind::With(val) => fmt::Debug::fmt(val, f),
+            ExprKind::If(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Assert(val) => fmt::Debug::fmt(val, f),
+            ExprKind::List(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Set(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Select(val) => fmt::Debug::fmt(val, f),
+            ExprKind::HasAttr(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Apply(val) => fmt::Debug::fmt(val, f),
+            ExprKind::Lambda(val) => fmt::Debug::fmt(val, f),
+        }
+    }
 }
-impl<'a> SetExpr<'a> {
-    pub fn new(elems: BTreeMap<&'a str, Expr<'a>>) -> Self {
-        Self { elems }
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetExpr {
+    pub elems: std::collections::BTreeMap<Rc<str>, Expr>,
+    /// Attributes written as `${name} = value;`, whose names are only known
+    /// once `name` is evaluated.
+    pub dynamic: Vec<(Expr, Expr)>,
+    /// Set for `rec { ... }`, whose attributes are in scope of each other.
+    pub rec: bool,
+    /// The sources of `inherit (src) ...;`, each bound once to a name no
+    /// identifier can spell, which the inherited attributes select from.
+    pub inherit_from: Vec<(Rc<str>, Expr)>,
+}
+impl SetExpr {
+    pub fn new(elems: BTreeMap<Rc<str>, Expr>, rec: bool) -> Self {
+        Self {
+            elems,
+            dynamic: vec![],
+            rec,
+            inherit_from: vec![],
+        }
+    }
+    fn from_borrowed(elems: BTreeMap<&str, Expr>, rec: bool) -> Self {
+        let elems = elems
+            .into_iter()
+            .map(|(name, expr)| (Rc::from(name), expr))
+            .collect();
+        Self::new(elems, rec)
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct ListExpr<'a> {
-    elems: Vec<Expr<'a>>,
+/// One segment of the attribute path on the left of a binding: `name`,
+/// `"name"` or `${expr}`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttrName {
+    Static(Rc<str>),
+    Dynamic(Expr),
 }
-impl<'a> ListExpr<'a> {
-    pub fn new(elems: Vec<Expr<'a>>) -> Self {
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ListExpr {
+    pub elems: Vec<Expr>,
+}
+impl ListExpr {
+    pub fn new(elems: Vec<Expr>) -> Self {
         Self { elems }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct WithExpr<'a> {
-    scope: Expr<'a>,
-    expr: Expr<'a>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct WithExpr {
+    pub scope: Expr,
+    pub expr: Expr,
 }
-impl<'a> WithExpr<'a> {
-    pub fn new(scope: Expr<'a>, expr: Expr<'a>) -> Self {
+impl WithExpr {
+    pub fn new(scope: Expr, expr: Expr) -> Self {
         Self { scope, expr }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct IfExpr<'a> {
-    cond: Expr<'a>,
-    truthy: Expr<'a>,
-    falsy: Expr<'a>,
-}
-impl<'a> IfExpr<'a> {
-    pub fn new(cond: Expr<'a>, truthy: Expr<'a>, falsy: Expr<'a>) -> Self {
+#[derive(Debug, PartialEq, Clone)]
+pub struct IfExpr {
+    pub cond: Expr,
+    pub truthy: Expr,
+    pub falsy: Expr,
+}
+impl IfExpr {
+    pub fn new(cond: Expr, truthy: Expr, falsy: Expr) -> Self {
         Self {
             cond,
             truthy,
@@ -260,52 +311,136 @@ impl<'a> IfExpr<'a> {
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct SelectExpr<'a> {
-    set: Expr<'a>,
-    field: IdentExpr<'a>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct AssertExpr {
+    pub cond: Expr,
+    pub body: Expr,
+    /// The condition as written, shown when the assertion fails. Only known
+    /// when the parser was given the source.
+    pub cond_text: Option<Rc<str>>,
+}
+impl AssertExpr {
+    pub fn new(cond: Expr, body: Expr) -> Self {
+        Self {
+            cond,
+            body,
+            cond_text: None,
+        }
+    }
+}
+
+/// `set.a.b.c`, or `set.a.b.c or default` when `default` is given.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SelectExpr {
+    pub set: Expr,
+    pub path: Vec<AttrName>,
+    pub default: Option<Expr>,
 }
-impl<'a> SelectExpr<'a> {
-    pub fn new(set: Expr<'a>, field: IdentExpr<'a>) -> Self {
-        Self { set, field }
+impl SelectExpr {
+    pub fn new(set: Expr, path: Vec<AttrName>, default: Option<Expr>) -> Self {
+        Self { set, path, default }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct ApplyExpr<'a> {
-    func: Expr<'a>,
-    arg: Expr<'a>,
+/// `set ? a.b.c`, testing whether the attribute path exists.
+#[derive(Debug, PartialEq, Clone)]
+pub struct HasAttrExpr {
+    pub set: Expr,
+    pub path: Vec<AttrName>,
 }
-impl<'a> ApplyExpr<'a> {
-    pub fn new(func: Expr<'a>, arg: Expr<'a>) -> Self {
+impl HasAttrExpr {
+    pub fn new(set: Expr, path: Vec<AttrName>) -> Self {
+        Self { set, path }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ApplyExpr {
+    pub func: Expr,
+    pub arg: Expr,
+}
+impl ApplyExpr {
+    pub fn new(func: Expr, arg: Expr) -> Self {
         Self { func, arg }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct LetExpr<'a> {
-    bindings: std::collections::BTreeMap<IdentExpr<'a>, Expr<'a>>,
-    body: Expr<'a>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct LambdaExpr {
+    pub formals: Formals,
+    pub body: Expr,
+}
+impl LambdaExpr {
+    pub fn new(formals: Formals, body: Expr) -> Self {
+        Self { formals, body }
+    }
+}
+
+/// Parameters of a lambda: either a plain `x: ...` identifier or a
+/// `{ a, b ? 2, ... }` set pattern, optionally bound with `args@`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Formals {
+    Ident(IdentExpr),
+    Set(SetFormals),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetFormals {
+    pub formals: Vec<Formal>,
+    pub ellipsis: bool,
+    pub bind: Option<IdentExpr>,
+}
+impl SetFormals {
+    pub fn new(formals: Vec<Formal>, ellipsis: bool, bind: Option<IdentExpr>) -> Self {
+        Self {
+            formals,
+            ellipsis,
+            bind,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Formal {
+    pub name: IdentExpr,
+    pub default: Option<Expr>,
 }
-impl<'a> LetExpr<'a> {
-    pub fn new(bindings: BTreeMap<IdentExpr<'a>, Expr<'a>>, body: Expr<'a>) -> Self {
-        Self { bindings, body }
+impl Formal {
+    pub fn new(name: IdentExpr, default: Option<Expr>) -> Self {
+        Self { name, default }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
+pub struct LetExpr {
+    pub bindings: std::collections::BTreeMap<IdentExpr, Expr>,
+    pub body: Expr,
+    /// The sources of `inherit (src) ...;`, as in `SetExpr`.
+    pub inherit_from: Vec<(Rc<str>, Expr)>,
+}
+impl LetExpr {
+    pub fn new(bindings: BTreeMap<IdentExpr, Expr>, body: Expr) -> Self {
+        Self {
+            bindings,
+            body,
+            inherit_from: vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum UnaryExprType {
     LogicalNegation(),
     ArithmNegation(),
 }
 
-#[derive(Debug, PartialEq)]
-pub struct UnaryExpr<'a> {
-    pub right: Expr<'a>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnaryExpr {
+    pub right: Expr,
     pub typ: UnaryExprType,
 }
-impl<'a> UnaryExpr<'a> {
-    pub fn new(right: Expr<'a>, typ: UnaryExprType) -> Self {
+impl UnaryExpr {
+    pub fn new(right: Expr, typ: UnaryExprType) -> Self {
         Self { right, typ }
     }
 }
@@ -324,82 +459,125 @@ pub enum BinaryExprType {
     And(),
     Or(),
     Arrow(),
-    Has(),
     Mult(),
     Div(),
     Update(),
 }
 
-#[derive(Debug, PartialEq)]
-pub struct BinaryExpr<'a> {
-    pub left: Expr<'a>,
-    pub right: Expr<'a>,
+impl fmt::Display for BinaryExprType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryExprType::Add() => "+",
+            BinaryExprType::Sub() => "-",
+            BinaryExprType::Equals() => "==",
+            BinaryExprType::NotEquals() => "!=",
+            BinaryExprType::More() => ">",
+            BinaryExprType::Less() => "<",
+            BinaryExprType::MoreOrEquals() => ">=",
+            BinaryExprType::LessOrEquals() => "<=",
+            BinaryExprType::Concat() => "++",
+            BinaryExprType::And() => "&&",
+            BinaryExprType::Or() => "||",
+            BinaryExprType::Arrow() => "->",
+            BinaryExprType::Mult() => "*",
+            BinaryExprType::Div() => "/",
+            BinaryExprType::Update() => "//",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct BinaryExpr {
+    pub left: Expr,
+    pub right: Expr,
     pub typ: BinaryExprType,
 }
-impl<'a> BinaryExpr<'a> {
-    pub fn new(left: Expr<'a>, right: Expr<'a>, typ: BinaryExprType) -> Self {
+impl BinaryExpr {
+    pub fn new(left: Expr, right: Expr, typ: BinaryExprType) -> Self {
         Self { left, right, typ }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct BindingExpr<'a> {
-    pub ident: IdentExpr<'a>,
-    pub expr: Expr<'a>,
+#[derive(Debug, PartialEq, Clone)]
+pub struct BindingExpr {
+    pub ident: IdentExpr,
+    pub expr: Expr,
 }
-impl<'a> BindingExpr<'a> {
-    pub fn new(ident: IdentExpr<'a>, expr: Expr<'a>) -> Self {
+impl BindingExpr {
+    pub fn new(ident: IdentExpr, expr: Expr) -> Self {
         Self { ident, expr }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct IdentExpr<'a> {
-    pub name: &'a str,
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
+pub struct IdentExpr {
+    pub name: Rc<str>,
 }
-impl<'a> IdentExpr<'a> {
-    pub fn new(name: &'a str) -> Self {
-        Self { name }
+impl IdentExpr {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+        }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum LiteralExpr<'a> {
-    Str(&'a str),
-    Path(&'a str),
-    NixPath(&'a str),
-    Int(i32),
-    Flo(f32),
+#[derive(Debug, PartialEq, Clone)]
+pub enum LiteralExpr {
+    Str(Rc<str>),
+    Path(Rc<str>),
+    NixPath(Rc<str>),
+    Int(i64),
+    Flo(f64),
     Bool(bool),
     Null(),
 }
 
-#[derive(Debug, PartialEq)]
+/// A string with `${...}` in it, e.g. `"hello ${name}!"`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct InterpolatedExpr {
+    pub parts: Vec<StrPart>,
+}
+impl InterpolatedExpr {
+    pub fn new(parts: Vec<StrPart>) -> Self {
+        Self { parts }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StrPart {
+    Literal(String),
+    Expr(Expr),
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct IntExpr {
-    val: i32,
+    val: i64,
 }
 impl IntExpr {
-    pub fn new(val: i32) -> Self {
+    pub fn new(val: i64) -> Self {
         Self { val }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct FloExpr {
-    val: f32,
+    val: f64,
 }
 impl FloExpr {
-    pub fn new(val: f32) -> Self {
+    pub fn new(val: f64) -> Self {
         Self { val }
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct StrExpr<'a> {
-    val: &'a str,
+#[derive(Debug, PartialEq, Clone)]
+pub struct StrExpr {
+    val: String,
 }
-impl<'a> StrExpr<'a> {
-    pub fn new(val: &'a str) -> Self {
-        Self { val }
+impl StrExpr {
+    pub fn new(val: &str) -> Self {
+        Self {
+            val: val.to_owned(),
+        }
     }
 }
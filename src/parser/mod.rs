@@ -2,6 +2,7 @@
 mod parser;
 
 mod ast;
+mod strings;
 mod tests_parser;
 
 pub use crate::parser::ast::*;
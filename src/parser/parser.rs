@@ -1,396 +1,934 @@
+use crate::lexer::span::Span;
+use crate::lexer::tokens::Token;
 use crate::lexer::tokens::{
     AdditiveOperator, ArithmComparison, LogicalComparison, MultiplicativeOperator, TokenType,
 };
 use crate::lexer::TokenStream;
 use crate::parser::ast::{
-    BinaryExpr, BindingExpr, Expr, FloExpr, IdentExpr, IntExpr, LetExpr, LiteralExpr, StrExpr,
-    UnaryExpr,
+    AssertExpr, AttrName, Expr, ExprKind, Formal, Formals, IdentExpr, LetExpr, SetExpr, SetFormals,
+    StrPart,
 };
+use crate::parser::strings::{decode_str, unescape_str};
+use crate::stack::grow_stack;
 use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::iter::Peekable;
+use std::rc::Rc;
 use std::slice::Iter;
 
 pub trait Parser<'a> {
-    fn parse(&mut self) -> Ast;
+    fn parse(&mut self) -> Result<Ast, ParseErrors<'a>>;
 }
-pub type Ast<'a> = Expr<'a>;
+pub type Ast = Expr;
+
+type ParseResult<'a, T> = Result<T, ParseError<'a>>;
 
 pub struct AstParser<'a> {
-    toks: &'a TokenStream<'a>,
-    iter: Peekable<Iter<'a, TokenType<'a>>>,
+    iter: Peekable<Iter<'a, Token<'a>>>,
+    prev_span: Span,
+    errors: Vec<ParseError<'a>>,
+    // The text the tokens were lexed from, if known.
+    source: Option<&'a str>,
+    // Number of `inherit (src)` sources seen so far, used to name them.
+    inherit_sources: usize,
 }
 impl<'a> Parser<'a> for AstParser<'a> {
-    fn parse(&mut self) -> Ast {
-        self.parse_expr()
+    fn parse(&mut self) -> Result<Ast, ParseErrors<'a>> {
+        let partial = match self.parse_expr() {
+            Ok(expr) => {
+                if self.peek().is_some() {
+                    let err = self.unexpected("end of input");
+                    self.errors.push(err);
+                }
+                Some(expr)
+            }
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        };
+
+        match partial {
+            Some(ast) if self.errors.is_empty() => Ok(ast),
+            partial => Err(ParseErrors {
+                errors: std::mem::take(&mut self.errors),
+                partial,
+            }),
+        }
     }
 }
 
 impl<'a> AstParser<'a> {
     pub fn new(toks: &'a TokenStream<'a>) -> Self {
         Self {
-            toks,
             iter: toks.iter().peekable(),
+            prev_span: Span::new(0, 0, 1, 1),
+            errors: vec![],
+            source: None,
+            inherit_sources: 0,
         }
     }
 
-    fn parse_expr(&mut self) -> Expr<'a> {
+    /// Gives the parser the text `toks` were lexed from, so that
+    /// expressions can keep parts of it for error messages.
+    pub fn with_source(mut self, source: &'a str) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    fn peek(&mut self) -> Option<&'a TokenType<'a>> {
+        self.iter.peek().map(|tok| &tok.typ)
+    }
+
+    fn next(&mut self) -> Option<&'a TokenType<'a>> {
+        let tok = self.iter.next()?;
+        self.prev_span = tok.span;
+        Some(&tok.typ)
+    }
+
+    // Span of the upcoming token, falling back to the last consumed one at EOF.
+    fn peek_span(&mut self) -> Span {
+        self.iter.peek().map_or(self.prev_span, |tok| tok.span)
+    }
+
+    // Builds an error describing the upcoming token without consuming it.
+    fn unexpected(&mut self, expected: &'static str) -> ParseError<'a> {
         match self.iter.peek() {
+            Some(tok) => ParseError::new(
+                ParseErrorKind::UnexpectedToken {
+                    expected,
+                    found: tok.typ.clone(),
+                },
+                tok.span,
+            ),
+            None => ParseError::new(ParseErrorKind::UnexpectedEof { expected }, self.prev_span),
+        }
+    }
+
+    fn expect(&mut self, typ: TokenType<'a>, expected: &'static str) -> ParseResult<'a, ()> {
+        if self.peek() == Some(&typ) {
+            self.next();
+            Ok(())
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    // Records the error and skips ahead to the next `;`, `}`, `]` or `in` that
+    // is not nested inside a bracketed expression. The synchronizing token is
+    // consumed unless it is the `closer` of the enclosing construct, which
+    // guarantees that every recovery makes progress.
+    fn recover(&mut self, err: ParseError<'a>, closer: &TokenType<'a>) {
+        self.errors.push(err);
+
+        let mut depth = 0usize;
+        while let Some(tok) = self.peek() {
+            match tok {
+                TokenType::OpenBrace
+                | TokenType::Interpolate
+                | TokenType::OpenSquare
+                | TokenType::OpenParen => depth += 1,
+                TokenType::CloseBrace | TokenType::CloseSquare | TokenType::CloseParen
+                    if depth > 0 =>
+                {
+                    depth -= 1
+                }
+                TokenType::Semicolon
+                | TokenType::CloseBrace
+                | TokenType::CloseSquare
+                | TokenType::In
+                    if depth == 0 =>
+                {
+                    if tok != closer {
+                        self.next();
+                    }
+                    return;
+                }
+                _ => (),
+            }
+            self.next();
+        }
+    }
+
+    // Every nested expression goes through here, so the stack is grown as
+    // needed for input of any depth.
+    fn parse_expr(&mut self) -> ParseResult<'a, Expr> {
+        grow_stack(|| self.parse_expr_kind())
+    }
+
+    fn parse_expr_kind(&mut self) -> ParseResult<'a, Expr> {
+        match self.peek() {
             Some(tok) => match tok {
                 TokenType::Let => self.parse_let(),
                 TokenType::With => self.parse_with(),
                 TokenType::If => self.parse_if(),
+                TokenType::Assert => self.parse_assert(),
+                TokenType::Ident(_)
+                    if matches!(self.peek_nth(1), Some(TokenType::Colon | TokenType::At)) =>
+                {
+                    self.parse_lambda()
+                }
+                TokenType::OpenBrace if self.at_set_formals() => self.parse_lambda(),
                 _ => self.parse_arrow(),
             },
-            _ => unimplemented!(),
+            None => Err(self.unexpected("expression")),
         }
     }
 
-    fn parse_arrow(&mut self) -> Expr<'a> {
-        let mut left = self.parse_or();
+    fn peek_nth(&self, n: usize) -> Option<&'a TokenType<'a>> {
+        self.iter.clone().nth(n).map(|tok| &tok.typ)
+    }
 
-        while let Some(TokenType::LogImpl) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_or();
-            left = Expr::new_or(left, right);
+    // Distinguishes `{ a, b ? 1, ... }: body` from a set literal by looking
+    // past the opening brace without consuming anything.
+    fn at_set_formals(&self) -> bool {
+        matches!(
+            (self.peek_nth(1), self.peek_nth(2)),
+            (
+                Some(TokenType::CloseBrace),
+                Some(TokenType::Colon | TokenType::At)
+            ) | (Some(TokenType::Ellipsis), _)
+                | (
+                    Some(TokenType::Ident(_)),
+                    Some(TokenType::Comma | TokenType::Has | TokenType::CloseBrace)
+                )
+        )
+    }
+
+    // Binary operators from the loosest to the tightest binding, following
+    // the nix manual: `->`, `||`, `&&`, `==`, comparisons, `//`, `+ -`,
+    // `* /`, `++`, `?` and unary `-`. `->`, `//` and `++` are right
+    // associative, comparisons and `?` not associative at all. `!` binds
+    // between `//` and `+ -`, but like `-` it can start any operand.
+    fn parse_arrow(&mut self) -> ParseResult<'a, Expr> {
+        let left = self.parse_or()?;
+
+        if let Some(TokenType::LogImpl) = self.peek() {
+            self.next();
+            let right = grow_stack(|| self.parse_arrow())?;
+            return Ok(Expr::new_implication(left, right));
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_or(&mut self) -> Expr<'a> {
-        let mut left = self.parse_and();
+    fn parse_or(&mut self) -> ParseResult<'a, Expr> {
+        let mut left = self.parse_and()?;
 
-        while let Some(TokenType::Or) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_and();
+        while let Some(TokenType::Or) = self.peek() {
+            self.next();
+            let right = self.parse_and()?;
             left = Expr::new_or(left, right);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_and(&mut self) -> Expr<'a> {
-        let mut left = self.parse_logical_comparison();
+    fn parse_and(&mut self) -> ParseResult<'a, Expr> {
+        let mut left = self.parse_logical_comparison()?;
 
-        while let Some(TokenType::And) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_logical_comparison();
+        while let Some(TokenType::And) = self.peek() {
+            self.next();
+            let right = self.parse_logical_comparison()?;
             left = Expr::new_and(left, right);
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_logical_comparison(&mut self) -> Expr<'a> {
-        let mut left = self.parse_arithm_comparison();
+    fn parse_logical_comparison(&mut self) -> ParseResult<'a, Expr> {
+        let left = self.parse_arithm_comparison()?;
 
-        while let Some(TokenType::LogicalComparison(op)) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_arithm_comparison();
-            match op {
-                LogicalComparison::CompareEquals => left = Expr::new_compare_equals(left, right),
-                LogicalComparison::CompareNotEquals => {
-                    left = Expr::new_compare_not_equals(left, right)
-                }
-            }
+        if let Some(TokenType::LogicalComparison(op)) = self.peek() {
+            self.next();
+            let right = self.parse_arithm_comparison()?;
+            return Ok(match op {
+                LogicalComparison::CompareEquals => Expr::new_compare_equals(left, right),
+                LogicalComparison::CompareNotEquals => Expr::new_compare_not_equals(left, right),
+            });
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_arithm_comparison(&mut self) -> Expr<'a> {
-        let mut left = self.parse_update();
-
-        while let Some(TokenType::ArithmComparison(op)) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_update();
-            match op {
-                ArithmComparison::More => left = Expr::new_compare_more(left, right),
-                ArithmComparison::Less => left = Expr::new_compare_less(left, right),
-                ArithmComparison::MoreOrEquals => {
-                    left = Expr::new_compare_more_or_equals(left, right)
-                }
-                ArithmComparison::LessOrEquals => {
-                    left = Expr::new_compare_less_or_equals(left, right)
-                }
-            }
+    fn parse_arithm_comparison(&mut self) -> ParseResult<'a, Expr> {
+        let left = self.parse_update()?;
+
+        if let Some(TokenType::ArithmComparison(op)) = self.peek() {
+            self.next();
+            let right = self.parse_update()?;
+            return Ok(match op {
+                ArithmComparison::More => Expr::new_compare_more(left, right),
+                ArithmComparison::Less => Expr::new_compare_less(left, right),
+                ArithmComparison::MoreOrEquals => Expr::new_compare_more_or_equals(left, right),
+                ArithmComparison::LessOrEquals => Expr::new_compare_less_or_equals(left, right),
+            });
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_update(&mut self) -> Expr<'a> {
-        let mut left = self.parse_not();
+    fn parse_update(&mut self) -> ParseResult<'a, Expr> {
+        let left = self.parse_additive()?;
 
-        while let Some(TokenType::Update) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_not();
-            left = Expr::new_update(left, right);
+        if let Some(TokenType::Update) = self.peek() {
+            self.next();
+            let right = grow_stack(|| self.parse_update())?;
+            return Ok(Expr::new_update(left, right));
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_not(&mut self) -> Expr<'a> {
-        if let Some(TokenType::LogicalNegation) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_additive();
-            return Expr::new_logical_negation(right);
-        }
+    fn parse_additive(&mut self) -> ParseResult<'a, Expr> {
+        let mut left = self.parse_multiplicative()?;
 
-        self.parse_additive()
-    }
-
-    fn parse_additive(&mut self) -> Expr<'a> {
-        let mut left = self.parse_multiplicative();
-
-        while let Some(TokenType::AdditiveOperator(op)) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_multiplicative();
+        while let Some(TokenType::AdditiveOperator(op)) = self.peek() {
+            self.next();
+            let right = self.parse_multiplicative()?;
             match op {
                 AdditiveOperator::Add => left = Expr::new_add(left, right),
                 AdditiveOperator::Sub => left = Expr::new_sub(left, right),
             }
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_multiplicative(&mut self) -> Expr<'a> {
-        let mut left = self.parse_concat();
+    fn parse_multiplicative(&mut self) -> ParseResult<'a, Expr> {
+        let mut left = self.parse_concat()?;
 
-        while let Some(TokenType::MultiplicativeOperator(op)) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_concat();
+        while let Some(TokenType::MultiplicativeOperator(op)) = self.peek() {
+            self.next();
+            let right = self.parse_concat()?;
             match op {
                 MultiplicativeOperator::Mult => left = Expr::new_mult(left, right),
                 MultiplicativeOperator::Div => left = Expr::new_div(left, right),
             }
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_concat(&mut self) -> Expr<'a> {
-        let mut left = self.parse_arithm_negation();
+    fn parse_concat(&mut self) -> ParseResult<'a, Expr> {
+        let left = self.parse_has()?;
 
-        while let Some(TokenType::Concat) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_arithm_negation();
-            left = Expr::new_concat(left, right);
+        if let Some(TokenType::Concat) = self.peek() {
+            self.next();
+            let right = grow_stack(|| self.parse_concat())?;
+            return Ok(Expr::new_concat(left, right));
         }
 
-        left
+        Ok(left)
     }
 
-    fn parse_has(&mut self) -> Expr<'a> {
-        let mut left = self.parse_application();
+    // `?` takes an attribute path rather than an expression on its right.
+    fn parse_has(&mut self) -> ParseResult<'a, Expr> {
+        let set = self.parse_arithm_negation()?;
 
-        while let Some(TokenType::Has) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_application();
-            left = Expr::new_has(left, right);
+        if let Some(TokenType::Has) = self.peek() {
+            self.next();
+            let path = self.parse_attr_path("attribute name after '?'")?;
+            let span = set.span.to(self.prev_span);
+            return Ok(Expr::new_has(set, path).spanned(span));
         }
 
-        left
+        Ok(set)
     }
 
-    fn parse_arithm_negation(&mut self) -> Expr<'a> {
-        if let Some(TokenType::ArithmNegation) = self.iter.peek() {
-            self.iter.next();
-            let right = self.parse_has();
-            return Expr::new_arithmetic_negation(right);
+    fn parse_arithm_negation(&mut self) -> ParseResult<'a, Expr> {
+        match self.peek() {
+            Some(TokenType::ArithmNegation) => {
+                let start = self.peek_span();
+                self.next();
+                let right = grow_stack(|| self.parse_arithm_negation())?;
+                Ok(Expr::new_arithmetic_negation(right).spanned(start.to(self.prev_span)))
+            }
+            Some(TokenType::LogicalNegation) => self.parse_not(),
+            _ => self.parse_application(),
         }
+    }
 
-        self.parse_has()
+    // `!` takes everything binding tighter than itself as its operand, so
+    // `1 + !a + b` is `1 + !(a + b)`, while `!a // b` is `(!a) // b`.
+    fn parse_not(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        self.next();
+        let right = grow_stack(|| self.parse_additive())?;
+        Ok(Expr::new_logical_negation(right).spanned(start.to(self.prev_span)))
     }
 
-    // TODO
-    fn parse_application(&mut self) -> Expr<'a> {
-        let mut expr = self.parse_selection();
+    // Application is left associative, so `f a b` is `(f a) b`. Literals
+    // and lists can never be called and don't take arguments.
+    fn parse_application(&mut self) -> ParseResult<'a, Expr> {
+        let mut expr = self.parse_selection()?;
 
-        if let Expr::Ident(_) | Expr::With(_) | Expr::Let(_) | Expr::Select(_) | Expr::Apply(_) =
-            expr
+        if let ExprKind::Literal(_) | ExprKind::Interpolated(_) | ExprKind::List(_) = *expr.kind {
+            return Ok(expr);
+        }
+        while let Some(
+            TokenType::Ident(_)
+            | TokenType::OpenParen
+            | TokenType::OpenBrace
+            | TokenType::OpenSquare
+            | TokenType::Rec
+            | TokenType::Int(_)
+            | TokenType::Flo(_)
+            | TokenType::Bool(_)
+            | TokenType::Null
+            | TokenType::StrLiteral(_)
+            | TokenType::IndStrLiteral(_)
+            | TokenType::StrStart
+            | TokenType::IndStrStart
+            | TokenType::Path(_)
+            | TokenType::NixPath(_),
+        ) = self.peek()
         {
-            while let Some(
-                TokenType::Ident(_)
-                | TokenType::OpenParen
-                | TokenType::OpenBrace
-                | TokenType::OpenSquare
-                | TokenType::Int(_)
-                | TokenType::Flo(_)
-                | TokenType::Path(_)
-                | TokenType::NixPath(_),
-            ) = self.iter.peek()
-            {
-                self.iter.next();
-                let arg = self.parse_selection();
-                expr = Expr::new_apply(expr, arg);
-            }
+            let arg = self.parse_selection()?;
+            expr = Expr::new_apply(expr, arg);
         }
 
-        if let Expr::Apply(_) = expr {
-            match self.iter.peek() {
-                Some(TokenType::Semicolon | TokenType::Has) => (),
-                None => (),
-                _ => panic!("Failed to parse function application"),
-            }
+        Ok(expr)
+    }
+
+    // `or` is only a keyword right after a selection; anywhere else it is
+    // an ordinary identifier.
+    fn parse_selection(&mut self) -> ParseResult<'a, Expr> {
+        let obj = self.parse_term()?;
+        if self.peek() != Some(&TokenType::Access) {
+            return Ok(obj);
         }
 
-        expr
+        self.next();
+        let path = self.parse_attr_path("attribute name after '.'")?;
+        let default = if let Some(TokenType::Ident("or")) = self.peek() {
+            self.next();
+            Some(self.parse_selection()?)
+        } else {
+            None
+        };
+        let span = obj.span.to(self.prev_span);
+        Ok(Expr::new_select(obj, path, default).spanned(span))
     }
 
-    fn parse_selection(&mut self) -> Expr<'a> {
-        let mut obj = self.parse_term();
+    fn parse_term(&mut self) -> ParseResult<'a, Expr> {
+        let expr = match self.peek() {
+            Some(TokenType::Ident(val)) => Expr::new_ident(val),
+            Some(TokenType::StrLiteral(raw)) => Expr::new_str(&unescape_str(raw)),
+            Some(TokenType::IndStrLiteral(raw)) => {
+                let parts = decode_str(vec![StrPart::Literal(raw.to_string())], true);
+                match parts.first() {
+                    Some(StrPart::Literal(s)) => Expr::new_str(s),
+                    _ => Expr::new_str(""),
+                }
+            }
+            Some(TokenType::Path(val)) => Expr::new_path(val),
+            Some(TokenType::NixPath(val)) => Expr::new_nix_path(val),
+            Some(TokenType::Bool(val)) => Expr::new_bool(*val),
+            Some(TokenType::Null) => Expr::new_null(),
+            Some(TokenType::Int(val)) => Expr::new_int(*val),
+            Some(TokenType::Flo(val)) => Expr::new_flo(*val),
+            Some(TokenType::StrStart | TokenType::IndStrStart) => return self.parse_interpolated(),
+            Some(TokenType::OpenSquare) => {
+                self.next();
+                return self.parse_list();
+            }
+            Some(TokenType::OpenBrace) => {
+                self.next();
+                return self.parse_set(false);
+            }
+            Some(TokenType::Rec) => {
+                let start = self.peek_span();
+                self.next();
+                self.expect(TokenType::OpenBrace, "'{' after 'rec'")?;
+                let set = self.parse_set(true)?;
+                return Ok(set.spanned(start.to(self.prev_span)));
+            }
+            Some(TokenType::OpenParen) => {
+                self.next();
+                let grouped_expr = self.parse_expr()?;
+                self.expect(TokenType::CloseParen, "')'")?;
+                return Ok(grouped_expr);
+            }
+            _ => return Err(self.unexpected("expression")),
+        };
+        self.next();
+        Ok(expr.spanned(self.prev_span))
+    }
 
-        if let Expr::Ident(_) | Expr::Set(_) = obj {
-            while let Some(TokenType::Access) = self.iter.peek() {
-                self.iter.next();
+    fn parse_interpolated(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        let indented = self.next() == Some(&TokenType::IndStrStart);
 
-                if let Some(TokenType::Ident(field_name)) = self.iter.next() {
-                    let field = IdentExpr::new(field_name);
-                    obj = Expr::new_select(obj, field);
-                } else {
-                    panic!("expected ident after dot")
+        let mut parts = vec![];
+        loop {
+            match self.peek() {
+                Some(TokenType::StrPart(s)) => {
+                    self.next();
+                    parts.push(StrPart::Literal(s.to_string()));
+                }
+                Some(TokenType::Interpolate) => {
+                    self.next();
+                    parts.push(StrPart::Expr(self.parse_expr()?));
+                    self.expect(TokenType::CloseBrace, "'}' after interpolation")?;
+                }
+                Some(TokenType::StrEnd) => {
+                    self.next();
+                    let parts = decode_str(parts, indented);
+                    return Ok(Expr::new_interpolated(parts).spanned(start.to(self.prev_span)));
+                }
+                _ => return Err(self.unexpected("end of string")),
+            }
+        }
+    }
+
+    fn parse_let(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        self.next();
+
+        let mut bindings = SetExpr::new(BTreeMap::new(), false);
+
+        while let Some(tok) = self.peek() {
+            if *tok == TokenType::In {
+                self.next();
+                let body = self.parse_expr()?;
+                // Let bindings become variables, so their names can't be
+                // dynamic.
+                for (name, _) in bindings.dynamic.iter() {
+                    self.errors.push(ParseError::new(
+                        ParseErrorKind::DynamicLetAttribute,
+                        name.span,
+                    ));
                 }
+                let mut let_expr = LetExpr::new(
+                    bindings
+                        .elems
+                        .into_iter()
+                        .map(|(name, expr)| (IdentExpr::new(&name), expr))
+                        .collect(),
+                    body,
+                );
+                let_expr.inherit_from = bindings.inherit_from;
+                return Ok(
+                    Expr::new(ExprKind::Let(Box::new(let_expr))).spanned(start.to(self.prev_span))
+                );
+            }
+
+            match self.parse_bindings(&mut bindings.inherit_from) {
+                Ok(new_bindings) => self.insert_bindings(&mut bindings, new_bindings),
+                Err(err) => self.recover(err, &TokenType::In),
             }
         }
 
-        obj
+        Err(self.unexpected("'in'"))
+    }
+
+    fn parse_lambda(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        let formals = match self.next() {
+            Some(TokenType::Ident(ident_name)) => {
+                if let Some(TokenType::At) = self.peek() {
+                    self.next();
+                    self.expect(TokenType::OpenBrace, "set pattern after '@'")?;
+                    let mut set_formals = self.parse_set_formals()?;
+                    check_bind(&set_formals, ident_name, start)?;
+                    set_formals.bind = Some(IdentExpr::new(ident_name));
+                    Formals::Set(set_formals)
+                } else {
+                    Formals::Ident(IdentExpr::new(ident_name))
+                }
+            }
+            _ => {
+                let mut set_formals = self.parse_set_formals()?;
+                if let Some(TokenType::At) = self.peek() {
+                    self.next();
+                    match self.peek() {
+                        Some(TokenType::Ident(ident_name)) => {
+                            self.next();
+                            check_bind(&set_formals, ident_name, self.prev_span)?;
+                            set_formals.bind = Some(IdentExpr::new(ident_name));
+                        }
+                        _ => return Err(self.unexpected("identifier after '@'")),
+                    }
+                }
+                Formals::Set(set_formals)
+            }
+        };
+
+        self.expect(TokenType::Colon, "':' after lambda formals")?;
+        let body = self.parse_expr()?;
+        Ok(Expr::new_lambda(formals, body).spanned(start.to(self.prev_span)))
     }
 
-    fn parse_term(&mut self) -> Expr<'a> {
-        match self.iter.next() {
-            Some(tok) => match *tok {
-                TokenType::Ident(val) => Expr::new_ident(val),
-                TokenType::StrLiteral(val) => Expr::new_str(val),
-                TokenType::Path(val) => Expr::new_path(val),
-                TokenType::OpenSquare => self.parse_list(),
-                TokenType::Int(val) => Expr::new_int(val),
-                TokenType::Flo(val) => Expr::new_flo(val),
-                TokenType::OpenBrace => self.parse_set(),
-                TokenType::OpenParen => {
-                    let grouped_expr = self.parse_expr();
-                    if let Some(TokenType::CloseParen) = self.iter.next() {
-                        grouped_expr
+    fn parse_set_formals(&mut self) -> ParseResult<'a, SetFormals> {
+        let mut formals: Vec<Formal> = vec![];
+        let mut ellipsis = false;
+
+        loop {
+            match self.peek() {
+                Some(TokenType::CloseBrace) => {
+                    self.next();
+                    break;
+                }
+                Some(TokenType::Ellipsis) => {
+                    self.next();
+                    ellipsis = true;
+                    self.expect(TokenType::CloseBrace, "'}' after '...'")?;
+                    break;
+                }
+                Some(TokenType::Ident(ident_name)) => {
+                    self.next();
+                    if formals.iter().any(|f| &*f.name.name == *ident_name) {
+                        return Err(ParseError::new(
+                            ParseErrorKind::DuplicateFormal(ident_name),
+                            self.prev_span,
+                        ));
+                    }
+                    let default = if let Some(TokenType::Has) = self.peek() {
+                        self.next();
+                        Some(self.parse_expr()?)
                     } else {
-                        panic!("expected closing paren for grouping");
+                        None
+                    };
+                    formals.push(Formal::new(IdentExpr::new(ident_name), default));
+
+                    match self.peek() {
+                        Some(TokenType::Comma) => {
+                            self.next();
+                        }
+                        Some(TokenType::CloseBrace) => (),
+                        _ => return Err(self.unexpected("',' or '}' in lambda formals")),
                     }
                 }
-                _ => panic!("Unexpected token type parsing a primitive value: {:?}", tok),
-            },
-            _ => panic!("Unexpected EOF when parsing a primitive value"),
+                _ => return Err(self.unexpected("lambda formal")),
+            }
         }
-    }
 
-    fn parse_let(&mut self) -> Expr<'a> {
-        self.iter.next();
+        Ok(SetFormals::new(formals, ellipsis, None))
+    }
 
-        let mut bindings: BTreeMap<IdentExpr<'a>, Expr<'a>> = BTreeMap::new();
+    fn parse_with(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        self.next();
 
-        while let Some(tok) = self.iter.peek() {
-            if **tok == TokenType::In {
-                self.iter.next();
-                return Expr::new_let(bindings, self.parse_expr());
-            }
+        let scope = self.parse_expr()?;
+        self.expect(TokenType::Semicolon, "';' after with expression")?;
+        let body = self.parse_expr()?;
+        Ok(Expr::new_with(scope, body).spanned(start.to(self.prev_span)))
+    }
 
-            let binding = self.parse_binding();
-            bindings.insert(binding.ident, binding.expr);
+    // A single `a.b.c = expr;`, or the bindings of an `inherit` statement.
+    fn parse_bindings(
+        &mut self,
+        inherit_from: &mut Vec<(Rc<str>, Expr)>,
+    ) -> ParseResult<'a, Vec<AttrBinding>> {
+        if let Some(TokenType::Inherit) = self.peek() {
+            return self.parse_inherit(inherit_from);
         }
 
-        panic!("failed to find in inside of let expr");
-    }
+        let start = self.peek_span();
+        let path = self.parse_attr_path("binding name")?;
+        let span = start.to(self.prev_span);
 
-    fn parse_with(&mut self) -> Expr<'a> {
-        self.iter.next();
+        self.expect(TokenType::Assign, "'='")?;
+        let expr = self.parse_expr()?;
+        self.expect(TokenType::Semicolon, "';' after binding")?;
+        Ok(vec![AttrBinding { path, expr, span }])
+    }
 
-        if let Some(tok) = self.iter.next() {
-            let scope = match tok {
-                TokenType::Ident(ident_name) => Expr::new_ident(ident_name),
-                TokenType::OpenBrace => self.parse_set(),
-                _ => unimplemented!(),
-            };
-            if let Some(tok) = self.iter.next() {
-                if *tok != TokenType::Semicolon {
-                    unimplemented!();
-                }
+    // `a.b.c`, where `expected` describes the first name.
+    fn parse_attr_path(&mut self, expected: &'static str) -> ParseResult<'a, Vec<AttrName>> {
+        let mut path = vec![self.parse_attr_name(expected)?];
+        while let Some(TokenType::Access) = self.peek() {
+            self.next();
+            path.push(self.parse_attr_name("attribute name after '.'")?);
+        }
+        Ok(path)
+    }
 
-                return Expr::new_with(scope, self.parse_expr());
+    fn parse_attr_name(&mut self, expected: &'static str) -> ParseResult<'a, AttrName> {
+        match self.peek() {
+            Some(TokenType::Ident(name)) => {
+                self.next();
+                Ok(AttrName::Static(Rc::from(*name)))
             }
-        };
-        unimplemented!();
-    }
-
-    fn parse_binding(&mut self) -> BindingExpr<'a> {
-        if let Some(tok) = self.iter.next() {
-            if let TokenType::Ident(ident_name) = tok {
-                if let Some(tok) = self.iter.next() {
-                    if let TokenType::Assign = tok {
-                        let ident = IdentExpr::new(ident_name);
-                        let binding_expr = self.parse_expr();
-                        self.iter.next();
-                        return BindingExpr::new(ident, binding_expr);
-                    } else {
-                        panic!("unexpected non-assign parsing binding");
-                    }
-                }
-            } else {
-                panic!("unexpected non-ident parsing binding: {:?}", tok);
+            // `true`, `false` and `null` are keywords but still valid names,
+            // as in `builtins.true`.
+            Some(TokenType::Bool(b)) => {
+                let name = if *b { "true" } else { "false" };
+                self.next();
+                Ok(AttrName::Static(Rc::from(name)))
+            }
+            Some(TokenType::Null) => {
+                self.next();
+                Ok(AttrName::Static(Rc::from("null")))
+            }
+            Some(TokenType::StrLiteral(raw)) => {
+                self.next();
+                Ok(AttrName::Static(Rc::from(unescape_str(raw))))
+            }
+            Some(TokenType::StrStart) => Ok(AttrName::Dynamic(self.parse_interpolated()?)),
+            Some(TokenType::Interpolate) => {
+                self.next();
+                let name = self.parse_expr()?;
+                self.expect(TokenType::CloseBrace, "'}' after interpolation")?;
+                Ok(AttrName::Dynamic(name))
             }
+            _ => Err(self.unexpected(expected)),
         }
-        panic!("unexpected EOF parsing binding");
     }
 
-    fn parse_set(&mut self) -> Expr<'a> {
-        let mut elems: BTreeMap<&'a str, Expr<'a>> = BTreeMap::new();
-        while let Some(&tok) = self.iter.peek() {
-            if *tok == TokenType::CloseBrace {
-                return Expr::new_set(elems);
+    // Merges each binding into `set`, recording an error for every path that
+    // was already defined.
+    fn insert_bindings(&mut self, set: &mut SetExpr, bindings: Vec<AttrBinding>) {
+        for binding in bindings {
+            let span = binding.span;
+            if let Err(path) = insert_attr(set, binding.path.into_iter(), binding.expr, span) {
+                self.errors.push(ParseError::new(
+                    ParseErrorKind::DuplicateAttribute(path),
+                    span,
+                ));
             }
+        }
+    }
 
-            let binding = self.parse_binding();
+    // `inherit a b;` binds each name to the variable of the same name in the
+    // enclosing scope, `inherit (src) a b;` desugars to `a = src.a; b = src.b;`
+    // with `src` added to `inherit_from`, so it is evaluated only once.
+    fn parse_inherit(
+        &mut self,
+        inherit_from: &mut Vec<(Rc<str>, Expr)>,
+    ) -> ParseResult<'a, Vec<AttrBinding>> {
+        self.next();
+        let src = if let Some(TokenType::OpenParen) = self.peek() {
+            self.next();
+            let src = self.parse_expr()?;
+            self.expect(TokenType::CloseParen, "')'")?;
+            let name = format!("<inherit {}>", self.inherit_sources);
+            self.inherit_sources += 1;
+            inherit_from.push((Rc::from(name.as_str()), src));
+            Some(name)
+        } else {
+            None
+        };
 
-            elems.insert(binding.ident.name, binding.expr);
+        let mut bindings = vec![];
+        while let Some(TokenType::Ident(name)) = self.peek() {
+            self.next();
+            let expr = match &src {
+                Some(src) => Expr::new_select(
+                    Expr::new_ident(src),
+                    vec![AttrName::Static(Rc::from(*name))],
+                    None,
+                ),
+                None => Expr::new_inherit(name),
+            };
+            bindings.push(AttrBinding {
+                path: vec![AttrName::Static(Rc::from(*name))],
+                expr: expr.spanned(self.prev_span),
+                span: self.prev_span,
+            });
         }
-        unimplemented!();
+        self.expect(TokenType::Semicolon, "';' after inherited names")?;
+        Ok(bindings)
     }
 
-    fn parse_list(&mut self) -> Expr<'a> {
-        let mut elems: Vec<Expr<'a>> = vec![];
-        while let Some(tok) = self.iter.peek() {
-            if **tok == TokenType::CloseSquare {
-                self.iter.next();
-                return Expr::new_list(elems);
+    fn parse_set(&mut self, rec: bool) -> ParseResult<'a, Expr> {
+        let start = self.prev_span;
+        let mut set = SetExpr::new(BTreeMap::new(), rec);
+        while let Some(tok) = self.peek() {
+            if *tok == TokenType::CloseBrace {
+                self.next();
+                return Ok(Expr::new(ExprKind::Set(set)).spanned(start.to(self.prev_span)));
+            }
+
+            match self.parse_bindings(&mut set.inherit_from) {
+                Ok(bindings) => self.insert_bindings(&mut set, bindings),
+                Err(err) => self.recover(err, &TokenType::CloseBrace),
             }
+        }
+
+        Err(self.unexpected("'}'"))
+    }
 
-            let elem = self.parse_term();
+    fn parse_list(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.prev_span;
+        let mut elems: Vec<Expr> = vec![];
+        while let Some(tok) = self.peek() {
+            if *tok == TokenType::CloseSquare {
+                self.next();
+                return Ok(Expr::new_list(elems).spanned(start.to(self.prev_span)));
+            }
 
-            elems.push(elem);
+            match self.parse_selection() {
+                Ok(elem) => elems.push(elem),
+                Err(err) => self.recover(err, &TokenType::CloseSquare),
+            }
         }
-        unimplemented!();
+
+        Err(self.unexpected("']'"))
     }
 
-    fn parse_if(&mut self) -> Expr<'a> {
-        self.iter.next();
-        let condition = self.parse_expr();
-        if let Some(tok) = self.iter.next() {
-            if *tok != TokenType::Then {
-                panic!(
-                    "if expression must contain then, but encountered: {:?}",
-                    tok
-                );
+    fn parse_if(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        self.next();
+        let condition = self.parse_expr()?;
+        self.expect(TokenType::Then, "'then'")?;
+        let truthy = self.parse_expr()?;
+        self.expect(TokenType::Else, "'else'")?;
+        let falsy = self.parse_expr()?;
+        Ok(Expr::new_if(condition, truthy, falsy).spanned(start.to(self.prev_span)))
+    }
+
+    fn parse_assert(&mut self) -> ParseResult<'a, Expr> {
+        let start = self.peek_span();
+        self.next();
+        let condition = self.parse_expr()?;
+        self.expect(TokenType::Semicolon, "';' after assertion")?;
+        let body = self.parse_expr()?;
+        let cond_text = self
+            .source
+            .and_then(|src| src.get(condition.span.start..condition.span.end))
+            .map(Rc::from);
+        let assert = AssertExpr {
+            cond_text,
+            ..AssertExpr::new(condition, body)
+        };
+        Ok(Expr::new(ExprKind::Assert(Box::new(assert))).spanned(start.to(self.prev_span)))
+    }
+}
+
+// A binding as written, before its path is merged into the enclosing set.
+struct AttrBinding {
+    path: Vec<AttrName>,
+    expr: Expr,
+    span: Span,
+}
+
+// Inserts `expr` at `path`, creating the intermediate sets on the way. Sets
+// assigned to the same name are merged the way nix does, so `a.b = 1;` and
+// `a = { c = 2; };` together define `a = { b = 1; c = 2; }`. Segments after a
+// dynamic one can't be merged with anything and always form a fresh set.
+// On a collision the dotted path that was defined twice is returned.
+fn insert_attr(
+    set: &mut SetExpr,
+    mut path: std::vec::IntoIter<AttrName>,
+    expr: Expr,
+    span: Span,
+) -> Result<(), String> {
+    let name = match path.next() {
+        Some(AttrName::Static(name)) => name,
+        Some(AttrName::Dynamic(name)) => {
+            let mut nested = SetExpr::new(BTreeMap::new(), false);
+            let value = if path.len() == 0 {
+                expr
+            } else {
+                insert_attr(&mut nested, path, expr, span)?;
+                Expr::new(ExprKind::Set(nested)).spanned(span)
+            };
+            set.dynamic.push((name, value));
+            return Ok(());
+        }
+        None => unreachable!("attribute paths have at least one segment"),
+    };
+    if path.len() == 0 {
+        let existing = match set.elems.get_mut(&name) {
+            Some(existing) => existing,
+            None => {
+                set.elems.insert(name, expr);
+                return Ok(());
             }
+        };
+        return match (Rc::make_mut(&mut existing.kind), &*expr.kind) {
+            (ExprKind::Set(existing), ExprKind::Set(new)) => {
+                for (attr, value) in new.elems.iter() {
+                    if existing.elems.contains_key(attr) {
+                        return Err(format!("{}.{}", name, attr));
+                    }
+                    existing.elems.insert(attr.clone(), value.clone());
+                }
+                existing.dynamic.extend(new.dynamic.iter().cloned());
+                existing
+                    .inherit_from
+                    .extend(new.inherit_from.iter().cloned());
+                Ok(())
+            }
+            _ => Err(name.to_string()),
+        };
+    }
+
+    let nested = set
+        .elems
+        .entry(name.clone())
+        .or_insert_with(|| Expr::new_set(BTreeMap::new()).spanned(span));
+    match Rc::make_mut(&mut nested.kind) {
+        ExprKind::Set(nested) => {
+            insert_attr(nested, path, expr, span).map_err(|path| format!("{}.{}", name, path))
         }
-        let truthy = self.parse_expr();
-        if let Some(tok) = self.iter.next() {
-            if *tok != TokenType::Else {
-                panic!(
-                    "if expression must contain else, but encountered: {:?}",
-                    tok
-                );
+        _ => Err(name.to_string()),
+    }
+}
+
+// The name bound with `@` holds the whole argument, so it can't also be
+// one of the formals.
+fn check_bind<'a>(formals: &SetFormals, bind: &'a str, span: Span) -> ParseResult<'a, ()> {
+    if formals.formals.iter().any(|f| &*f.name.name == bind) {
+        return Err(ParseError::new(ParseErrorKind::DuplicateFormal(bind), span));
+    }
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseErrorKind<'a> {
+    UnexpectedToken {
+        expected: &'static str,
+        found: TokenType<'a>,
+    },
+    UnexpectedEof {
+        expected: &'static str,
+    },
+    DuplicateFormal(&'a str),
+    DuplicateAttribute(String),
+    DynamicLetAttribute,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError<'a> {
+    pub kind: ParseErrorKind<'a>,
+    pub span: Span,
+}
+impl<'a> ParseError<'a> {
+    pub fn new(kind: ParseErrorKind<'a>, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+impl Display for ParseError<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found {:?}", expected, found)?
+            }
+            ParseErrorKind::UnexpectedEof { expected } => {
+                write!(f, "expected {}, found end of input", expected)?
+            }
+            ParseErrorKind::DuplicateFormal(name) => {
+                write!(f, "duplicate formal function argument '{}'", name)?
+            }
+            ParseErrorKind::DuplicateAttribute(path) => {
+                write!(f, "attribute '{}' already defined", path)?
+            }
+            ParseErrorKind::DynamicLetAttribute => {
+                write!(f, "dynamic attributes not allowed in let")?
             }
         }
-        let falsy = self.parse_expr();
-        Expr::new_if(condition, truthy, falsy)
+        write!(f, " at {}", self.span)
     }
 }
+
+/// Every syntax error found in one pass, along with whatever could still be
+/// parsed. `partial` is `None` when the error left no expression to recover.
+#[derive(Debug, PartialEq)]
+pub struct ParseErrors<'a> {
+    pub errors: Vec<ParseError<'a>>,
+    pub partial: Option<Ast>,
+}
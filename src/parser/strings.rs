@@ -0,0 +1,161 @@
+use crate::parser::ast::StrPart;
+
+/// Turns the raw parts of a string literal into its value: escape
+/// sequences are decoded and, for `''` strings, indentation is stripped.
+pub fn decode_str(parts: Vec<StrPart>, indented: bool) -> Vec<StrPart> {
+    let parts = if indented {
+        strip_indentation(parts)
+    } else {
+        parts
+    };
+    let unescape = if indented {
+        unescape_ind_str
+    } else {
+        unescape_str
+    };
+
+    parts
+        .into_iter()
+        .filter_map(|part| match part {
+            StrPart::Literal(raw) => {
+                let s = unescape(&raw);
+                (!s.is_empty()).then_some(StrPart::Literal(s))
+            }
+            part => Some(part),
+        })
+        .collect()
+}
+
+/// Decodes `\n`, `\r`, `\t` and the backslash escapes of any other
+/// character in a `"..."` string.
+pub fn unescape_str(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(unescape_char(escaped)),
+                None => out.push('\\'),
+            },
+            ch => out.push(ch),
+        }
+    }
+    out
+}
+
+// Decodes `'''` into `''`, `''$` into `$` and `''\` followed by a character
+// the way `"..."` strings treat a backslash.
+fn unescape_ind_str(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(pos) = rest.find("''") {
+        out.push_str(&rest[..pos]);
+        let mut chars = rest[pos + 2..].chars();
+        match chars.next() {
+            Some('\'') => out.push_str("''"),
+            Some('$') => out.push('$'),
+            Some('\\') => {
+                if let Some(escaped) = chars.next() {
+                    out.push(unescape_char(escaped));
+                }
+            }
+            Some(ch) => {
+                out.push_str("''");
+                out.push(ch);
+            }
+            None => out.push_str("''"),
+        }
+        rest = chars.as_str();
+    }
+    out.push_str(rest);
+    out
+}
+
+fn unescape_char(escaped: char) -> char {
+    match escaped {
+        'n' => '\n',
+        'r' => '\r',
+        't' => '\t',
+        ch => ch,
+    }
+}
+
+// Removes a first line holding nothing but spaces, the indentation shared
+// by every line that has some text, and the spaces of a blank last line.
+// Interpolations count as text. This runs before escapes are decoded, so
+// that an escaped tab or space is never taken for indentation.
+fn strip_indentation(mut parts: Vec<StrPart>) -> Vec<StrPart> {
+    if let Some(StrPart::Literal(first)) = parts.first_mut() {
+        let spaces = first.len() - first.trim_start_matches(' ').len();
+        if first[spaces..].starts_with('\n') {
+            first.drain(..=spaces);
+        }
+    }
+
+    let mut min_indent = usize::MAX;
+    let mut at_line_start = true;
+    let mut indent = 0;
+    for part in parts.iter() {
+        match part {
+            StrPart::Literal(s) => {
+                for ch in s.chars() {
+                    match (at_line_start, ch) {
+                        (true, ' ') => indent += 1,
+                        (_, '\n') => {
+                            at_line_start = true;
+                            indent = 0;
+                        }
+                        (true, _) => {
+                            min_indent = min_indent.min(indent);
+                            at_line_start = false;
+                        }
+                        (false, _) => (),
+                    }
+                }
+            }
+            StrPart::Expr(_) => {
+                if at_line_start {
+                    min_indent = min_indent.min(indent);
+                    at_line_start = false;
+                }
+            }
+        }
+    }
+
+    let mut at_line_start = true;
+    let mut dropped = 0;
+    for part in parts.iter_mut() {
+        match part {
+            StrPart::Literal(s) => {
+                let mut stripped = String::with_capacity(s.len());
+                for ch in s.chars() {
+                    match (at_line_start, ch) {
+                        (true, ' ') if dropped < min_indent => dropped += 1,
+                        (true, ' ') => stripped.push(ch),
+                        (_, '\n') => {
+                            stripped.push(ch);
+                            at_line_start = true;
+                            dropped = 0;
+                        }
+                        (true, _) => {
+                            stripped.push(ch);
+                            at_line_start = false;
+                        }
+                        (false, _) => stripped.push(ch),
+                    }
+                }
+                *s = stripped;
+            }
+            StrPart::Expr(_) => at_line_start = false,
+        }
+    }
+
+    if let Some(StrPart::Literal(last)) = parts.last_mut() {
+        if let Some(pos) = last.rfind('\n') {
+            if last[pos + 1..].chars().all(|ch| ch == ' ') {
+                last.truncate(pos + 1);
+            }
+        }
+    }
+    parts
+}
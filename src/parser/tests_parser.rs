@@ -1,15 +1,110 @@
 #[cfg(test)]
 mod tests {
+    use crate::lexer::span::Span;
     use crate::lexer::tokens::*;
     use crate::lexer::*;
     use crate::parser::ast::*;
     use crate::parser::*;
 
     use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    fn spanless<'a>(types: &[TokenType<'a>]) -> Vec<Token<'a>> {
+        types
+            .iter()
+            .map(|typ| Token::new(typ.clone(), Span::default()))
+            .collect()
+    }
+
+    // Lexes and parses `input` with every span left out, so that inputs
+    // differing only in redundant parentheses give equal trees.
+    fn parse_spanless(input: &str) -> Expr {
+        let mut lexer = Lexer::new(input);
+        let types: Vec<TokenType> = lexer
+            .tokenize()
+            .unwrap()
+            .iter()
+            .map(|tok| tok.typ.clone())
+            .collect();
+        let toks = spanless(&types);
+        let mut parser = AstParser::new(&toks);
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_operator_precedence() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            // select and application bind tightest
+            ("f a.b c", "(f (a.b)) c"),
+            ("-f x", "-(f x)"),
+            ("- a ? b", "(-a) ? b"),
+            ("-a.b ? c", "(-(a.b)) ? c"),
+            ("a ? b ++ c", "(a ? b) ++ c"),
+            ("a ++ b ++ c", "a ++ (b ++ c)"),
+            ("a * b ++ c", "a * (b ++ c)"),
+            ("a * b / c", "(a * b) / c"),
+            ("a + b * c", "a + (b * c)"),
+            ("a - b + c", "(a - b) + c"),
+            ("a * -b", "a * (-b)"),
+            ("!a + b", "!(a + b)"),
+            ("!!a", "!(!a)"),
+            ("!a // b", "(!a) // b"),
+            ("1 + !true", "1 + (!true)"),
+            ("1 + !a + b", "1 + (!(a + b))"),
+            ("a * !b", "a * (!b)"),
+            ("-!x", "-(!x)"),
+            ("-!x + 1", "-(!(x + 1))"),
+            ("!a ? b", "!(a ? b)"),
+            ("a // b // c", "a // (b // c)"),
+            ("a // b < c", "(a // b) < c"),
+            ("a < b == c", "(a < b) == c"),
+            ("a == b && c", "(a == b) && c"),
+            ("!a == b", "(!a) == b"),
+            ("a && b || c", "(a && b) || c"),
+            ("a || b && c", "a || (b && c)"),
+            ("a || b -> c", "(a || b) -> c"),
+            ("a -> b -> c", "a -> (b -> c)"),
+            ("a -> b || c && !d", "a -> (b || (c && (!d)))"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(parse_spanless(input), parse_spanless(want), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_non_associative_operators() {
+        let test_cases: Vec<(&str, TokenType)> = vec![
+            (
+                "a < b < c",
+                TokenType::ArithmComparison(ArithmComparison::Less),
+            ),
+            (
+                "a == b != c",
+                TokenType::LogicalComparison(LogicalComparison::CompareNotEquals),
+            ),
+            ("a ? b ? c", TokenType::Has),
+        ];
+
+        for (input, found) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let toks = lexer.tokenize().unwrap();
+            let err = AstParser::new(toks).parse().unwrap_err();
+            assert_eq!(
+                err.errors[0].kind,
+                ParseErrorKind::UnexpectedToken {
+                    expected: "end of input",
+                    found,
+                },
+                "{}",
+                input
+            );
+        }
+    }
 
     #[test]
     fn parse_valid_binary_statements() {
-        let test_cases: Vec<(&TokenStream, Expr)> = vec![
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
             (
                 &[
                     TokenType::Int(4),
@@ -52,9 +147,10 @@ mod tests {
         ];
 
         for (input, want) in test_cases {
-            let mut parser = AstParser::new(input);
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
 
-            let got = parser.parse();
+            let got = parser.parse().unwrap();
             assert_eq!(got, want);
         }
     }
@@ -77,7 +173,7 @@ mod tests {
             Expr::new_list(vec![Expr::new_str("elem1"), Expr::new_str("elem2")]),
         );
 
-        let test_cases: Vec<(&TokenStream, Expr)> = vec![
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
             (
                 &[
                     TokenType::Let,
@@ -128,16 +224,17 @@ mod tests {
         ];
 
         for (input, want) in test_cases {
-            let mut parser = AstParser::new(input);
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
 
-            let got = parser.parse();
+            let got = parser.parse().unwrap();
             assert_eq!(got, want);
         }
     }
 
     #[test]
     fn parse_valid_with_statements() {
-        let test_cases: Vec<(&TokenStream, Expr)> = vec![(
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![(
             &[
                 TokenType::With,
                 TokenType::Ident("pkgs"),
@@ -153,16 +250,17 @@ mod tests {
         )];
 
         for (input, want) in test_cases {
-            let mut parser = AstParser::new(input);
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
 
-            let got = parser.parse();
+            let got = parser.parse().unwrap();
             assert_eq!(got, want);
         }
     }
 
     #[test]
     fn parse_valid_if_statements() {
-        let test_cases: Vec<(&TokenStream, Expr)> = vec![(
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![(
             &[
                 TokenType::If,
                 TokenType::Int(1),
@@ -186,22 +284,870 @@ mod tests {
         )];
 
         for (input, want) in test_cases {
-            let mut parser = AstParser::new(input);
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_select_statements() {
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::Access,
+                    TokenType::StrLiteral("c-d"),
+                    TokenType::Access,
+                    TokenType::Interpolate,
+                    TokenType::Ident("e"),
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_select(
+                    Expr::new_ident("a"),
+                    vec![
+                        AttrName::Static(Rc::from("b")),
+                        AttrName::Static(Rc::from("c-d")),
+                        AttrName::Dynamic(Expr::new_ident("e")),
+                    ],
+                    None,
+                ),
+            ),
+            (
+                &[
+                    TokenType::OpenParen,
+                    TokenType::Ident("f"),
+                    TokenType::CloseParen,
+                    TokenType::Access,
+                    TokenType::Ident("x"),
+                    TokenType::Ident("or"),
+                    TokenType::Ident("y"),
+                    TokenType::Access,
+                    TokenType::Ident("z"),
+                ],
+                Expr::new_select(
+                    Expr::new_ident("f"),
+                    vec![AttrName::Static(Rc::from("x"))],
+                    Some(Expr::new_select(
+                        Expr::new_ident("y"),
+                        vec![AttrName::Static(Rc::from("z"))],
+                        None,
+                    )),
+                ),
+            ),
+            (
+                &[
+                    TokenType::OpenSquare,
+                    TokenType::CloseSquare,
+                    TokenType::Access,
+                    TokenType::Ident("a"),
+                    TokenType::Ident("or"),
+                    TokenType::Null,
+                ],
+                Expr::new_select(
+                    Expr::new_list(vec![]),
+                    vec![AttrName::Static(Rc::from("a"))],
+                    Some(Expr::new_null()),
+                ),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
 
-            let got = parser.parse();
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_assert_statements() {
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::Assert,
+                    TokenType::Ident("x"),
+                    TokenType::Semicolon,
+                    TokenType::Int(1),
+                ],
+                Expr::new_assert(Expr::new_ident("x"), Expr::new_int(1)),
+            ),
+            (
+                &[
+                    TokenType::Assert,
+                    TokenType::Ident("s"),
+                    TokenType::Has,
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::StrLiteral("b"),
+                    TokenType::Semicolon,
+                    TokenType::Assert,
+                    TokenType::Bool(true),
+                    TokenType::Semicolon,
+                    TokenType::Ident("s"),
+                ],
+                Expr::new_assert(
+                    Expr::new_has(
+                        Expr::new_ident("s"),
+                        vec![
+                            AttrName::Static(Rc::from("a")),
+                            AttrName::Static(Rc::from("b")),
+                        ],
+                    ),
+                    Expr::new_assert(Expr::new_bool(true), Expr::new_ident("s")),
+                ),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_apply_statements() {
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::Ident("f"),
+                    TokenType::Ident("x"),
+                    TokenType::StrLiteral("y"),
+                ],
+                Expr::new_apply(
+                    Expr::new_apply(Expr::new_ident("f"), Expr::new_ident("x")),
+                    Expr::new_str("y"),
+                ),
+            ),
+            (
+                &[
+                    TokenType::Ident("f"),
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::OpenSquare,
+                    TokenType::Int(1),
+                    TokenType::CloseSquare,
+                    TokenType::AdditiveOperator(AdditiveOperator::Add),
+                    TokenType::Int(2),
+                ],
+                Expr::new_add(
+                    Expr::new_apply(
+                        Expr::new_apply(
+                            Expr::new_ident("f"),
+                            Expr::new_select(
+                                Expr::new_ident("a"),
+                                vec![AttrName::Static(Rc::from("b"))],
+                                None,
+                            ),
+                        ),
+                        Expr::new_list(vec![Expr::new_int(1)]),
+                    ),
+                    Expr::new_int(2),
+                ),
+            ),
+            (
+                &[
+                    TokenType::OpenParen,
+                    TokenType::Ident("x"),
+                    TokenType::Colon,
+                    TokenType::Ident("x"),
+                    TokenType::CloseParen,
+                    TokenType::OpenBrace,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_apply(
+                    Expr::new_lambda(Formals::Ident(IdentExpr::new("x")), Expr::new_ident("x")),
+                    Expr::new_set(BTreeMap::new()),
+                ),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_lambda_statements() {
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::Ident("x"),
+                    TokenType::Colon,
+                    TokenType::Ident("x"),
+                    TokenType::AdditiveOperator(AdditiveOperator::Add),
+                    TokenType::Int(1),
+                ],
+                Expr::new_lambda(
+                    Formals::Ident(IdentExpr::new("x")),
+                    Expr::new_add(Expr::new_ident("x"), Expr::new_int(1)),
+                ),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Comma,
+                    TokenType::Ident("b"),
+                    TokenType::Has,
+                    TokenType::Int(2),
+                    TokenType::Comma,
+                    TokenType::Ellipsis,
+                    TokenType::CloseBrace,
+                    TokenType::Colon,
+                    TokenType::Ident("a"),
+                ],
+                Expr::new_lambda(
+                    Formals::Set(SetFormals::new(
+                        vec![
+                            Formal::new(IdentExpr::new("a"), None),
+                            Formal::new(IdentExpr::new("b"), Some(Expr::new_int(2))),
+                        ],
+                        true,
+                        None,
+                    )),
+                    Expr::new_ident("a"),
+                ),
+            ),
+            (
+                &[
+                    TokenType::Ident("args"),
+                    TokenType::At,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Comma,
+                    TokenType::Ellipsis,
+                    TokenType::CloseBrace,
+                    TokenType::Colon,
+                    TokenType::Ident("a"),
+                ],
+                Expr::new_lambda(
+                    Formals::Set(SetFormals::new(
+                        vec![Formal::new(IdentExpr::new("a"), None)],
+                        true,
+                        Some(IdentExpr::new("args")),
+                    )),
+                    Expr::new_ident("a"),
+                ),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::CloseBrace,
+                    TokenType::At,
+                    TokenType::Ident("args"),
+                    TokenType::Colon,
+                    TokenType::Ident("args"),
+                ],
+                Expr::new_lambda(
+                    Formals::Set(SetFormals::new(vec![], false, Some(IdentExpr::new("args")))),
+                    Expr::new_ident("args"),
+                ),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
             assert_eq!(got, want);
         }
     }
 
     #[test]
     fn parse_valid_sets_statements() {
-        let test_cases: Vec<(&TokenStream, Expr)> = vec![];
+        let mut test1 = BTreeMap::new();
+        test1.insert("a", Expr::new_int(1));
+        test1.insert("b", Expr::new_ident("a"));
+
+        let mut test2 = BTreeMap::new();
+        test2.insert("a", Expr::new_int(1));
+        test2.insert("b", Expr::new_ident("a"));
+
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[TokenType::OpenBrace, TokenType::CloseBrace],
+                Expr::new_set(BTreeMap::new()),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Ident("a"),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_set(test1),
+            ),
+            (
+                &[
+                    TokenType::Rec,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Ident("a"),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_rec_set(test2),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_inherit_statements() {
+        let mut test1 = BTreeMap::new();
+        test1.insert("a", Expr::new_inherit("a"));
+        test1.insert("b", Expr::new_inherit("b"));
+
+        let mut test2 = SetExpr::new(BTreeMap::new(), false);
+        test2.elems.insert(
+            Rc::from("a"),
+            Expr::new_select(
+                Expr::new_ident("<inherit 0>"),
+                vec![AttrName::Static(Rc::from("a"))],
+                None,
+            ),
+        );
+        test2.elems.insert(
+            Rc::from("b"),
+            Expr::new_select(
+                Expr::new_ident("<inherit 0>"),
+                vec![AttrName::Static(Rc::from("b"))],
+                None,
+            ),
+        );
+        test2.elems.insert(Rc::from("c"), Expr::new_int(1));
+        test2
+            .inherit_from
+            .push((Rc::from("<inherit 0>"), Expr::new_ident("src")));
+
+        let mut test3 = BTreeMap::new();
+        test3.insert(IdentExpr::new("x"), Expr::new_inherit("x"));
+
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Inherit,
+                    TokenType::Ident("a"),
+                    TokenType::Ident("b"),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_set(test1),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Inherit,
+                    TokenType::OpenParen,
+                    TokenType::Ident("src"),
+                    TokenType::CloseParen,
+                    TokenType::Ident("a"),
+                    TokenType::Ident("b"),
+                    TokenType::Semicolon,
+                    TokenType::Ident("c"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new(ExprKind::Set(test2)),
+            ),
+            (
+                &[
+                    TokenType::Let,
+                    TokenType::Inherit,
+                    TokenType::Ident("x"),
+                    TokenType::Semicolon,
+                    TokenType::In,
+                    TokenType::Ident("x"),
+                ],
+                Expr::new_let(test3, Expr::new_ident("x")),
+            ),
+        ];
 
         for (input, want) in test_cases {
-            let mut parser = AstParser::new(input);
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
 
-            let got = parser.parse();
+            let got = parser.parse().unwrap();
             assert_eq!(got, want);
         }
     }
+
+    #[test]
+    fn parse_valid_attr_path_statements() {
+        let mut inner = BTreeMap::new();
+        inner.insert("enable", Expr::new_bool(true));
+        inner.insert("port", Expr::new_int(80));
+        let mut nginx = BTreeMap::new();
+        nginx.insert("nginx", Expr::new_set(inner));
+        let mut test1 = BTreeMap::new();
+        test1.insert("services", Expr::new_set(nginx));
+
+        let mut quoted = BTreeMap::new();
+        quoted.insert("x", Expr::new_int(1));
+        let mut test2 = BTreeMap::new();
+        test2.insert("foo-bar", Expr::new_set(quoted));
+
+        let mut dynamic = SetExpr::new(BTreeMap::new(), false);
+        dynamic.dynamic.push((
+            Expr::new_ident("name"),
+            Expr::new_set(BTreeMap::from([("x", Expr::new_int(1))])),
+        ));
+
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("services"),
+                    TokenType::Access,
+                    TokenType::Ident("nginx"),
+                    TokenType::Access,
+                    TokenType::Ident("enable"),
+                    TokenType::Assign,
+                    TokenType::Bool(true),
+                    TokenType::Semicolon,
+                    TokenType::Ident("services"),
+                    TokenType::Access,
+                    TokenType::Ident("nginx"),
+                    TokenType::Assign,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("port"),
+                    TokenType::Assign,
+                    TokenType::Int(80),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_set(test1),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::StrLiteral("foo-bar"),
+                    TokenType::Access,
+                    TokenType::Ident("x"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new_set(test2),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Interpolate,
+                    TokenType::Ident("name"),
+                    TokenType::CloseBrace,
+                    TokenType::Access,
+                    TokenType::Ident("x"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                Expr::new(ExprKind::Set(dynamic)),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_valid_interpolated_statements() {
+        let test_cases: Vec<(&[TokenType], Expr)> = vec![
+            (
+                &[
+                    TokenType::StrStart,
+                    TokenType::StrPart("Hello, "),
+                    TokenType::Interpolate,
+                    TokenType::Ident("name"),
+                    TokenType::CloseBrace,
+                    TokenType::StrPart("!"),
+                    TokenType::StrEnd,
+                ],
+                Expr::new_interpolated(vec![
+                    StrPart::Literal("Hello, ".to_owned()),
+                    StrPart::Expr(Expr::new_ident("name")),
+                    StrPart::Literal("!".to_owned()),
+                ]),
+            ),
+            (
+                &[
+                    TokenType::StrStart,
+                    TokenType::Interpolate,
+                    TokenType::Ident("a"),
+                    TokenType::AdditiveOperator(AdditiveOperator::Add),
+                    TokenType::Ident("b"),
+                    TokenType::CloseBrace,
+                    TokenType::StrEnd,
+                ],
+                Expr::new_interpolated(vec![StrPart::Expr(Expr::new_add(
+                    Expr::new_ident("a"),
+                    Expr::new_ident("b"),
+                ))]),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want);
+        }
+    }
+
+    #[test]
+    fn parse_string_escapes() {
+        let test_cases: Vec<(&str, Expr)> = vec![
+            (
+                r#""a\n\t\"b\" \${c} $${d} \e""#,
+                Expr::new_str("a\n\t\"b\" ${c} $${d} e"),
+            ),
+            (
+                "''\n    one\n      two\n    ''' ''$ ''\\t\n  ''",
+                Expr::new_str("one\n  two\n'' $ \t\n"),
+            ),
+            ("''  ''", Expr::new_str("")),
+            (
+                "''\n  ${a}\n    b ''${c}''",
+                Expr::new_interpolated(vec![
+                    StrPart::Expr(Expr::new_ident("a")),
+                    StrPart::Literal("\n  b ${c}".to_owned()),
+                ]),
+            ),
+            (
+                r#""\${a}${b}""#,
+                Expr::new_interpolated(vec![
+                    StrPart::Literal("${a}".to_owned()),
+                    StrPart::Expr(Expr::new_ident("b")),
+                ]),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(input);
+            let toks = lexer.tokenize().unwrap();
+            let types: Vec<TokenType> = toks.iter().map(|tok| tok.typ.clone()).collect();
+            let toks = spanless(&types);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap();
+            assert_eq!(got, want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_spans() {
+        let input = "let\n  x = 1 + 2;\nin [ x ]";
+        let mut lexer = Lexer::new(input);
+        let toks = lexer.tokenize().unwrap();
+        let mut parser = AstParser::new(toks);
+        let got = parser.parse().unwrap();
+
+        assert_eq!(got.span, Span::new(0, input.len(), 1, 1));
+        match &*got.kind {
+            ExprKind::Let(let_expr) => {
+                assert_eq!(let_expr.body.span, Span::new(20, 25, 3, 4));
+                let binding = let_expr.bindings.get(&IdentExpr::new("x")).unwrap();
+                assert_eq!(binding.span, Span::new(10, 15, 2, 7));
+            }
+            _ => panic!("expected let expression, got {:?}", got),
+        }
+    }
+
+    #[test]
+    fn parse_reports_every_error() {
+        let input = "{\n  a = 1;\n  b = ;\n  c = [ 1 ) 2 ];\n  3 = 4;\n  d = 5;\n}";
+        let mut lexer = Lexer::new(input);
+        let toks = lexer.tokenize().unwrap();
+        let mut parser = AstParser::new(toks);
+        let got = parser.parse().unwrap_err();
+
+        let found: Vec<(&ParseErrorKind, usize)> =
+            got.errors.iter().map(|e| (&e.kind, e.span.line)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    &ParseErrorKind::UnexpectedToken {
+                        expected: "expression",
+                        found: TokenType::Semicolon
+                    },
+                    3
+                ),
+                (
+                    &ParseErrorKind::UnexpectedToken {
+                        expected: "expression",
+                        found: TokenType::CloseParen
+                    },
+                    4
+                ),
+                (
+                    &ParseErrorKind::UnexpectedToken {
+                        expected: "binding name",
+                        found: TokenType::Int(3)
+                    },
+                    5
+                ),
+            ]
+        );
+
+        let mut want = BTreeMap::new();
+        want.insert("a", Expr::new_int(1));
+        want.insert("c", Expr::new_list(vec![Expr::new_int(1)]));
+        want.insert("d", Expr::new_int(5));
+        let types: Vec<TokenType> = toks.iter().map(|tok| tok.typ.clone()).collect();
+        let toks = spanless(&types);
+        let mut parser = AstParser::new(&toks);
+        assert_eq!(
+            parser.parse().unwrap_err().partial,
+            Some(Expr::new_set(want))
+        );
+    }
+
+    #[test]
+    fn parse_invalid_statements() {
+        let test_cases: Vec<(&[TokenType], ParseErrorKind)> = vec![
+            (
+                &[TokenType::OpenParen, TokenType::Int(1)],
+                ParseErrorKind::UnexpectedEof { expected: "')'" },
+            ),
+            (
+                &[TokenType::Int(1), TokenType::Int(2)],
+                ParseErrorKind::UnexpectedToken {
+                    expected: "end of input",
+                    found: TokenType::Int(2),
+                },
+            ),
+            (
+                &[
+                    TokenType::Let,
+                    TokenType::Ident("x"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::In,
+                    TokenType::Ident("x"),
+                ],
+                ParseErrorKind::UnexpectedToken {
+                    expected: "';' after binding",
+                    found: TokenType::In,
+                },
+            ),
+            (
+                &[
+                    TokenType::If,
+                    TokenType::Bool(true),
+                    TokenType::Else,
+                    TokenType::Int(1),
+                ],
+                ParseErrorKind::UnexpectedToken {
+                    expected: "'then'",
+                    found: TokenType::Else,
+                },
+            ),
+            (
+                &[TokenType::Assert, TokenType::Bool(true), TokenType::Int(1)],
+                ParseErrorKind::UnexpectedToken {
+                    expected: "';' after assertion",
+                    found: TokenType::Int(1),
+                },
+            ),
+            (
+                &[TokenType::Ident("s"), TokenType::Has, TokenType::Int(1)],
+                ParseErrorKind::UnexpectedToken {
+                    expected: "attribute name after '?'",
+                    found: TokenType::Int(1),
+                },
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Comma,
+                    TokenType::Ident("a"),
+                    TokenType::CloseBrace,
+                    TokenType::Colon,
+                    TokenType::Ident("a"),
+                ],
+                ParseErrorKind::DuplicateFormal("a"),
+            ),
+            (
+                &[
+                    TokenType::Ident("args"),
+                    TokenType::At,
+                    TokenType::OpenBrace,
+                    TokenType::Ident("args"),
+                    TokenType::CloseBrace,
+                    TokenType::Colon,
+                    TokenType::Ident("args"),
+                ],
+                ParseErrorKind::DuplicateFormal("args"),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::CloseBrace,
+                    TokenType::At,
+                    TokenType::Ident("a"),
+                    TokenType::Colon,
+                    TokenType::Ident("a"),
+                ],
+                ParseErrorKind::DuplicateFormal("a"),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Int(2),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                ParseErrorKind::DuplicateAttribute("a.b".to_owned()),
+            ),
+            (
+                &[
+                    TokenType::Let,
+                    TokenType::Ident("a"),
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::Ident("a"),
+                    TokenType::Access,
+                    TokenType::Ident("b"),
+                    TokenType::Assign,
+                    TokenType::Int(2),
+                    TokenType::Semicolon,
+                    TokenType::In,
+                    TokenType::Ident("a"),
+                ],
+                ParseErrorKind::DuplicateAttribute("a".to_owned()),
+            ),
+            (
+                &[
+                    TokenType::OpenBrace,
+                    TokenType::Inherit,
+                    TokenType::Ident("a"),
+                    TokenType::Semicolon,
+                    TokenType::Ident("a"),
+                    TokenType::Assign,
+                    TokenType::Int(2),
+                    TokenType::Semicolon,
+                    TokenType::CloseBrace,
+                ],
+                ParseErrorKind::DuplicateAttribute("a".to_owned()),
+            ),
+            (
+                &[
+                    TokenType::Let,
+                    TokenType::Interpolate,
+                    TokenType::StrLiteral("a"),
+                    TokenType::CloseBrace,
+                    TokenType::Assign,
+                    TokenType::Int(2),
+                    TokenType::Semicolon,
+                    TokenType::In,
+                    TokenType::Int(1),
+                ],
+                ParseErrorKind::DynamicLetAttribute,
+            ),
+            (
+                &[
+                    TokenType::Let,
+                    TokenType::StrStart,
+                    TokenType::Interpolate,
+                    TokenType::StrLiteral("a"),
+                    TokenType::CloseBrace,
+                    TokenType::StrEnd,
+                    TokenType::Assign,
+                    TokenType::Int(1),
+                    TokenType::Semicolon,
+                    TokenType::In,
+                    TokenType::Int(2),
+                ],
+                ParseErrorKind::DynamicLetAttribute,
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let toks = spanless(input);
+            let mut parser = AstParser::new(&toks);
+
+            let got = parser.parse().unwrap_err();
+            assert_eq!(got.errors.len(), 1, "{:?}", got.errors);
+            assert_eq!(got.errors[0].kind, want);
+        }
+    }
+
+    #[test]
+    fn parse_deeply_nested_input() {
+        let depth = 100000;
+        let test_cases: Vec<String> = vec![
+            format!("{}1{}", "(".repeat(depth), ")".repeat(depth)),
+            format!("{}true", "!".repeat(depth)),
+            format!("{}1", "-".repeat(depth)),
+            format!("{}x", "x: ".repeat(depth)),
+            format!("{}[]", "[] ++ ".repeat(depth)),
+            format!("1{}", " + 1".repeat(2 * depth)),
+        ];
+
+        for input in test_cases {
+            let mut lexer = Lexer::new(&input);
+            let toks = lexer.tokenize().unwrap();
+            assert!(AstParser::new(toks).parse().is_ok());
+        }
+    }
 }
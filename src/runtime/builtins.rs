@@ -0,0 +1,446 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use anyhow::Result;
+
+use crate::lexer::span::Span;
+use crate::parser::Expr;
+use crate::runtime::env::{Env, Thunk, Value};
+use crate::runtime::error::{EvalError, EvalErrorKind};
+use crate::runtime::interpreter::Interpreter;
+
+type BuiltinFn = fn(&Interpreter, &[Value], Span) -> Result<Value>;
+
+/// A function implemented natively. It is called once `arity` arguments
+/// have been applied; until then applying it only collects the argument.
+#[derive(Clone)]
+pub struct Builtin {
+    pub name: &'static str,
+    arity: usize,
+    func: BuiltinFn,
+    args: Vec<Value>,
+}
+impl Builtin {
+    pub fn new(name: &'static str, arity: usize, func: BuiltinFn) -> Self {
+        Self {
+            name,
+            arity,
+            func,
+            args: vec![],
+        }
+    }
+
+    /// Whether some, but not all, of the arguments have been applied.
+    pub fn is_partial(&self) -> bool {
+        !self.args.is_empty()
+    }
+
+    pub(crate) fn apply(mut self, interp: &Interpreter, arg: Value, span: Span) -> Result<Value> {
+        self.args.push(arg);
+        if self.args.len() < self.arity {
+            return Ok(Value::Builtin(self));
+        }
+        (self.func)(interp, &self.args, span)
+    }
+}
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Builtin({}, {:?})", self.name, self.args)
+    }
+}
+impl PartialEq for Builtin {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.args == other.args
+    }
+}
+
+const BUILTINS: &[(&str, usize, BuiltinFn)] = &[
+    ("abort", 1, abort),
+    ("all", 2, all),
+    ("any", 2, any),
+    ("concatLists", 1, concat_lists),
+    ("concatMap", 2, concat_map),
+    ("elem", 2, elem),
+    ("elemAt", 2, elem_at),
+    ("filter", 2, filter),
+    ("foldl'", 3, foldl),
+    ("genList", 2, gen_list),
+    ("groupBy", 2, group_by),
+    ("head", 1, head),
+    ("isAttrs", 1, is_attrs),
+    ("isBool", 1, is_bool),
+    ("isFloat", 1, is_float),
+    ("isFunction", 1, is_function),
+    ("isInt", 1, is_int),
+    ("isList", 1, is_list),
+    ("isNull", 1, is_null),
+    ("isPath", 1, is_path),
+    ("isString", 1, is_string),
+    ("length", 1, length),
+    ("listToAttrs", 1, list_to_attrs),
+    ("map", 2, map),
+    ("partition", 2, partition),
+    ("sort", 2, sort),
+    ("tail", 1, tail),
+    ("throw", 1, throw),
+    ("toString", 1, to_string),
+    ("typeOf", 1, type_of),
+];
+
+/// Builtins that can be used without the `builtins.` prefix.
+const GLOBALS: &[&str] = &["abort", "map", "throw", "toString"];
+
+/// The `builtins` set.
+pub fn builtins() -> BTreeMap<Rc<str>, Value> {
+    let mut attrs: BTreeMap<Rc<str>, Value> = BUILTINS
+        .iter()
+        .map(|&(name, arity, func)| {
+            (
+                Rc::from(name),
+                Value::Builtin(Builtin::new(name, arity, func)),
+            )
+        })
+        .collect();
+    attrs.insert(Rc::from("null"), Value::Null());
+    attrs.insert(Rc::from("true"), Value::Bool(true));
+    attrs.insert(Rc::from("false"), Value::Bool(false));
+    attrs
+}
+
+/// The outermost scope every evaluation starts from, holding `builtins`
+/// and the builtins visible without a prefix.
+pub fn global_env() -> Rc<Env> {
+    let env = Env::new(None, false);
+    let attrs = builtins();
+    for name in GLOBALS.iter().chain(&["null", "true", "false"]) {
+        env.set(Rc::from(*name), attrs[*name].clone())
+            .expect("builtin names are unique");
+    }
+    env.set(Rc::from("builtins"), Value::Set(Rc::new(attrs)))
+        .expect("builtin names are unique");
+    Rc::new(env)
+}
+
+fn type_of(interp: &Interpreter, args: &[Value], _: Span) -> Result<Value> {
+    let v = interp.force(args[0].clone())?;
+    Ok(Value::Str(Rc::from(v.type_name())))
+}
+
+// Builds a builtin testing what type its argument is.
+macro_rules! type_predicate {
+    ($name:ident, $pat:pat) => {
+        fn $name(interp: &Interpreter, args: &[Value], _: Span) -> Result<Value> {
+            let v = interp.force(args[0].clone())?;
+            Ok(Value::Bool(matches!(v, $pat)))
+        }
+    };
+}
+type_predicate!(is_attrs, Value::Set(_));
+type_predicate!(is_bool, Value::Bool(_));
+type_predicate!(is_float, Value::Flo(_));
+type_predicate!(
+    is_function,
+    Value::Func(_) | Value::PFunc(_) | Value::Builtin(_)
+);
+type_predicate!(is_int, Value::Int(_));
+type_predicate!(is_list, Value::List(_));
+type_predicate!(is_null, Value::Null());
+type_predicate!(is_path, Value::Path(_) | Value::NixPath(_));
+type_predicate!(is_string, Value::Str(_));
+
+fn to_string(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    Ok(Value::Str(
+        interp.coerce_more_to_string(args[0].clone(), span)?,
+    ))
+}
+
+fn throw(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let msg = expect_str(interp, &args[0], span)?;
+    Err(EvalError::new(EvalErrorKind::Thrown(msg.to_string()), span).into())
+}
+
+fn abort(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let msg = expect_str(interp, &args[0], span)?;
+    Err(EvalError::new(EvalErrorKind::Aborted(msg.to_string()), span).into())
+}
+
+fn length(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let len = expect_list(interp, &args[0], span)?.len();
+    Ok(Value::Int(len as i64))
+}
+
+fn head(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[0], span)?;
+    match elems.first() {
+        Some(v) => interp.force(v.clone()),
+        None => Err(EvalError::new(EvalErrorKind::EmptyList("head"), span).into()),
+    }
+}
+
+fn tail(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[0], span)?;
+    match elems.split_first() {
+        Some((_, rest)) => Ok(Value::List(Rc::new(rest.to_vec()))),
+        None => Err(EvalError::new(EvalErrorKind::EmptyList("tail"), span).into()),
+    }
+}
+
+fn elem_at(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[0], span)?;
+    let i = expect_int(interp, &args[1], span)?;
+    match usize::try_from(i).ok().and_then(|i| elems.get(i)) {
+        Some(v) => interp.force(v.clone()),
+        None => Err(EvalError::new(EvalErrorKind::IndexOutOfBounds(i), span).into()),
+    }
+}
+
+fn elem(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[1], span)?;
+    for v in elems.iter() {
+        if interp.eval_equal(&args[0], v, span)? {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+// Elements are only computed when they are used, so `map` and `genList`
+// don't fail on elements that are never looked at.
+fn map(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[1], span)?;
+    let mapped = elems
+        .iter()
+        .map(|v| delay_apply(&args[0], v.clone(), span))
+        .collect();
+    Ok(Value::List(Rc::new(mapped)))
+}
+
+fn gen_list(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let n = expect_int(interp, &args[1], span)?;
+    if n < 0 {
+        return Err(EvalError::new(EvalErrorKind::NegativeListSize(n), span).into());
+    }
+    let elems = (0..n)
+        .map(|i| delay_apply(&args[0], Value::Int(i), span))
+        .collect();
+    Ok(Value::List(Rc::new(elems)))
+}
+
+fn filter(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let (right, _) = split_by(interp, &args[0], &args[1], span)?;
+    Ok(Value::List(Rc::new(right)))
+}
+
+fn partition(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let (right, wrong) = split_by(interp, &args[0], &args[1], span)?;
+    let attrs = BTreeMap::from([
+        (Rc::from("right"), Value::List(Rc::new(right))),
+        (Rc::from("wrong"), Value::List(Rc::new(wrong))),
+    ]);
+    Ok(Value::Set(Rc::new(attrs)))
+}
+
+// Separates the elements `pred` holds for from the ones it doesn't,
+// keeping their order.
+fn split_by(
+    interp: &Interpreter,
+    pred: &Value,
+    list: &Value,
+    span: Span,
+) -> Result<(Vec<Value>, Vec<Value>)> {
+    let (mut right, mut wrong) = (vec![], vec![]);
+    for v in expect_list(interp, list, span)?.iter() {
+        if expect_bool(interp, &interp.apply(pred.clone(), v.clone(), span)?, span)? {
+            right.push(v.clone());
+        } else {
+            wrong.push(v.clone());
+        }
+    }
+    Ok((right, wrong))
+}
+
+fn all(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    for v in expect_list(interp, &args[1], span)?.iter() {
+        if !expect_bool(
+            interp,
+            &interp.apply(args[0].clone(), v.clone(), span)?,
+            span,
+        )? {
+            return Ok(Value::Bool(false));
+        }
+    }
+    Ok(Value::Bool(true))
+}
+
+fn any(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    for v in expect_list(interp, &args[1], span)?.iter() {
+        if expect_bool(
+            interp,
+            &interp.apply(args[0].clone(), v.clone(), span)?,
+            span,
+        )? {
+            return Ok(Value::Bool(true));
+        }
+    }
+    Ok(Value::Bool(false))
+}
+
+// `foldl'` forces the accumulator at every step, so long lists don't build
+// up a chain of pending additions.
+fn foldl(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let mut acc = interp.force(args[1].clone())?;
+    for v in expect_list(interp, &args[2], span)?.iter() {
+        let partial = interp.apply(args[0].clone(), acc, span)?;
+        acc = interp.force(interp.apply(partial, v.clone(), span)?)?;
+    }
+    Ok(acc)
+}
+
+fn concat_lists(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let mut elems = vec![];
+    for list in expect_list(interp, &args[0], span)?.iter() {
+        elems.extend(expect_list(interp, list, span)?.iter().cloned());
+    }
+    Ok(Value::List(Rc::new(elems)))
+}
+
+fn concat_map(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let mut elems = vec![];
+    for v in expect_list(interp, &args[1], span)?.iter() {
+        let list = interp.apply(args[0].clone(), v.clone(), span)?;
+        elems.extend(expect_list(interp, &list, span)?.iter().cloned());
+    }
+    Ok(Value::List(Rc::new(elems)))
+}
+
+fn sort(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let elems = expect_list(interp, &args[1], span)?;
+    let less = |a: &Value, b: &Value| {
+        let partial = interp.apply(args[0].clone(), a.clone(), span)?;
+        expect_bool(interp, &interp.apply(partial, b.clone(), span)?, span)
+    };
+    Ok(Value::List(Rc::new(merge_sort(&elems, &less)?)))
+}
+
+// A stable sort that stops at the first error from the comparator, which
+// `slice::sort_by` has no way to do.
+fn merge_sort(
+    elems: &[Value],
+    less: &dyn Fn(&Value, &Value) -> Result<bool>,
+) -> Result<Vec<Value>> {
+    if elems.len() <= 1 {
+        return Ok(elems.to_vec());
+    }
+    let (left, right) = elems.split_at(elems.len() / 2);
+    let (left, right) = (merge_sort(left, less)?, merge_sort(right, less)?);
+    let mut sorted = Vec::with_capacity(elems.len());
+    let (mut l, mut r) = (left.into_iter().peekable(), right.into_iter().peekable());
+    while let (Some(a), Some(b)) = (l.peek(), r.peek()) {
+        if less(b, a)? {
+            sorted.extend(r.next());
+        } else {
+            sorted.extend(l.next());
+        }
+    }
+    sorted.extend(l.chain(r));
+    Ok(sorted)
+}
+
+fn group_by(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let mut groups: BTreeMap<Rc<str>, Vec<Value>> = BTreeMap::new();
+    for v in expect_list(interp, &args[1], span)?.iter() {
+        let name = expect_str(
+            interp,
+            &interp.apply(args[0].clone(), v.clone(), span)?,
+            span,
+        )?;
+        groups.entry(name).or_default().push(v.clone());
+    }
+    let attrs = groups
+        .into_iter()
+        .map(|(name, elems)| (name, Value::List(Rc::new(elems))))
+        .collect();
+    Ok(Value::Set(Rc::new(attrs)))
+}
+
+// When a name appears more than once, the first value given for it wins.
+fn list_to_attrs(interp: &Interpreter, args: &[Value], span: Span) -> Result<Value> {
+    let mut attrs = BTreeMap::new();
+    for v in expect_list(interp, &args[0], span)?.iter() {
+        let pair = expect_set(interp, v, span)?;
+        let name = expect_str(interp, get_attr(&pair, "name", span)?, span)?;
+        let value = get_attr(&pair, "value", span)?;
+        attrs.entry(name).or_insert_with(|| value.clone());
+    }
+    Ok(Value::Set(Rc::new(attrs)))
+}
+
+// Applies `func` to `arg` only once the result is forced.
+fn delay_apply(func: &Value, arg: Value, span: Span) -> Value {
+    let env = Env::new(None, false);
+    env.set(Rc::from("f"), func.clone())
+        .and_then(|env| env.set(Rc::from("x"), arg))
+        .expect("names are distinct");
+    let expr = Expr::new_apply(Expr::new_ident("f"), Expr::new_ident("x")).spanned(span);
+    Value::Thunk(Rc::new(Thunk::new(expr, Rc::new(env))))
+}
+
+fn get_attr<'v>(attrs: &'v BTreeMap<Rc<str>, Value>, name: &str, span: Span) -> Result<&'v Value> {
+    attrs.get(name).ok_or_else(|| {
+        EvalError::new(
+            EvalErrorKind::AttributeMissing {
+                name: name.to_owned(),
+                suggestions: vec![],
+            },
+            span,
+        )
+        .into()
+    })
+}
+
+fn type_mismatch(expected: &'static str, found: &Value, span: Span) -> anyhow::Error {
+    EvalError::new(
+        EvalErrorKind::TypeMismatch {
+            expected,
+            found: found.type_name(),
+        },
+        span,
+    )
+    .into()
+}
+
+fn expect_str(interp: &Interpreter, v: &Value, span: Span) -> Result<Rc<str>> {
+    match interp.force(v.clone())? {
+        Value::Str(s) => Ok(s),
+        v => Err(type_mismatch("string", &v, span)),
+    }
+}
+
+fn expect_int(interp: &Interpreter, v: &Value, span: Span) -> Result<i64> {
+    match interp.force(v.clone())? {
+        Value::Int(i) => Ok(i),
+        v => Err(type_mismatch("int", &v, span)),
+    }
+}
+
+fn expect_bool(interp: &Interpreter, v: &Value, span: Span) -> Result<bool> {
+    match interp.force(v.clone())? {
+        Value::Bool(b) => Ok(b),
+        v => Err(type_mismatch("bool", &v, span)),
+    }
+}
+
+fn expect_list(interp: &Interpreter, v: &Value, span: Span) -> Result<Rc<Vec<Value>>> {
+    match interp.force(v.clone())? {
+        Value::List(elems) => Ok(elems),
+        v => Err(type_mismatch("list", &v, span)),
+    }
+}
+
+fn expect_set(interp: &Interpreter, v: &Value, span: Span) -> Result<Rc<BTreeMap<Rc<str>, Value>>> {
+    match interp.force(v.clone())? {
+        Value::Set(attrs) => Ok(attrs),
+        v => Err(type_mismatch("set", &v, span)),
+    }
+}
@@ -1,89 +1,357 @@
-use std::collections::BTreeSet;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::rc::Rc;
 
-use crate::parser::{Ast, Expr, IdentExpr, LiteralExpr};
+use crate::lexer::span::Span;
+use crate::parser::{Expr, IdentExpr, LambdaExpr, LiteralExpr};
+use crate::runtime::builtins::Builtin;
+use crate::stack::grow_stack;
 use anyhow::{bail, Error, Result};
 
-#[derive(Debug, PartialEq)]
-pub enum Value<'a> {
-    Dep(BTreeSet<&'a str>),
-    Str(&'a str),
-    Path(&'a str),
-    NixPath(&'a str),
-    Int(i32),
-    Flo(f32),
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Dep(BTreeSet<String>),
+    Str(Rc<str>),
+    Path(Rc<str>),
+    NixPath(Rc<str>),
+    Int(i64),
+    Flo(f64),
     Bool(bool),
     Null(),
-    List(),
-    Set(),
-    Func(),
-    PFunc(),
+    List(Rc<Vec<Value>>),
+    Set(Rc<BTreeMap<Rc<str>, Value>>),
+    Func(Closure),
+    PFunc(Closure),
+    Builtin(Builtin),
+    Thunk(Rc<Thunk>),
 }
 
-impl<'a> From<&'a LiteralExpr<'a>> for Value<'a> {
-    fn from(l: &'a LiteralExpr<'a>) -> Self {
+/// A lazily evaluated value: an expression together with the scope it was
+/// written in. It is evaluated the first time the thunk is forced and the
+/// result replaces it for every later use.
+pub struct Thunk {
+    pub state: RefCell<ThunkState>,
+}
+impl Thunk {
+    pub fn new(expr: Expr, env: Rc<Env>) -> Self {
+        Self {
+            state: RefCell::new(ThunkState::Pending(expr, env)),
+        }
+    }
+}
+// Scopes often contain thunks that point back at them, so the captured
+// environment is left out.
+impl fmt::Debug for Thunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &*self.state.borrow() {
+            ThunkState::Pending(expr, _) => write!(f, "Thunk(Pending({:?}))", expr),
+            ThunkState::Blackhole(expr) => write!(f, "Thunk(Blackhole({:?}))", expr),
+            ThunkState::Done(v) => write!(f, "Thunk(Done({:?}))", v),
+        }
+    }
+}
+// Values can be nested arbitrarily deep through thunks, so freeing one may
+// recurse further than the native stack allows without growing it.
+impl Drop for Thunk {
+    fn drop(&mut self) {
+        let state = std::mem::replace(self.state.get_mut(), ThunkState::Done(Value::Null()));
+        grow_stack(move || drop(state));
+    }
+}
+// Thunks are only ever equal to themselves; comparing what they evaluate
+// to requires forcing them first.
+impl PartialEq for Thunk {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+pub enum ThunkState {
+    Pending(Expr, Rc<Env>),
+    /// The thunk is being forced; reaching it again means its value
+    /// depends on itself.
+    Blackhole(Expr),
+    Done(Value),
+}
+
+/// A function value: a lambda together with the scope it was defined in.
+/// `Value::Func` wraps lambdas with a single identifier formal,
+/// `Value::PFunc` the ones taking a set pattern.
+#[derive(Clone)]
+pub struct Closure {
+    pub lambda: Rc<LambdaExpr>,
+    pub env: Rc<Env>,
+}
+impl Closure {
+    pub fn new(lambda: Rc<LambdaExpr>, env: Rc<Env>) -> Self {
+        Self { lambda, env }
+    }
+}
+// Like thunks, closures usually live in the scope they capture, so the
+// environment is left out.
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Closure({:?})", self.lambda)
+    }
+}
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.lambda, &other.lambda) && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+impl Value {
+    /// The name `builtins.typeOf` would report for this value.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Dep(_) => "dependency",
+            Value::Str(_) => "string",
+            Value::Path(_) | Value::NixPath(_) => "path",
+            Value::Int(_) => "int",
+            Value::Flo(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Null() => "null",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::Func(_) | Value::PFunc(_) | Value::Builtin(_) => "lambda",
+            Value::Thunk(thunk) => match &*thunk.state.borrow() {
+                ThunkState::Done(v) => v.type_name(),
+                ThunkState::Pending(..) | ThunkState::Blackhole(_) => "thunk",
+            },
+        }
+    }
+
+    /// Identity of the list or set behind this value, used to spot values
+    /// that contain themselves.
+    pub(crate) fn container_ptr(&self) -> Option<*const ()> {
+        match self {
+            Value::List(elems) => Some(Rc::as_ptr(elems) as *const ()),
+            Value::Set(attrs) => Some(Rc::as_ptr(attrs) as *const ()),
+            _ => None,
+        }
+    }
+
+    /// Renders the value with sets and lists nested deeper than `depth`
+    /// abbreviated to `{ ... }` and `[ ... ]`, the way `nix repl` does.
+    pub fn display_depth(&self, depth: usize) -> String {
+        struct Depth<'v>(&'v Value, usize);
+        impl fmt::Display for Depth<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.write_depth(f, self.1, &mut vec![])
+            }
+        }
+        Depth(self, depth).to_string()
+    }
+
+    // Sets and lists already being printed further up are shown as
+    // `«repeated»` instead of recursing forever.
+    fn write_depth(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+        path: &mut Vec<*const ()>,
+    ) -> fmt::Result {
+        if let Some(ptr) = self.container_ptr() {
+            if path.contains(&ptr) {
+                return write!(f, "«repeated»");
+            }
+            path.push(ptr);
+            let res = grow_stack(|| self.write_value(f, depth, path));
+            path.pop();
+            return res;
+        }
+        self.write_value(f, depth, path)
+    }
+
+    fn write_value(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+        path: &mut Vec<*const ()>,
+    ) -> fmt::Result {
+        match self {
+            Value::Dep(deps) => write!(f, "<DEPENDS ON {:?}>", deps),
+            Value::Str(s) => write_escaped(f, s),
+            Value::Path(p) | Value::NixPath(p) => write!(f, "{}", p),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Flo(fl) => write!(f, "{}", format_float(*fl)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Null() => write!(f, "null"),
+            Value::List(elems) if elems.is_empty() => write!(f, "[ ]"),
+            Value::List(_) if depth == 0 => write!(f, "[ ... ]"),
+            Value::List(elems) => {
+                write!(f, "[ ")?;
+                for elem in elems.iter() {
+                    elem.write_depth(f, depth - 1, path)?;
+                    write!(f, " ")?;
+                }
+                write!(f, "]")
+            }
+            Value::Set(attrs) if attrs.is_empty() => write!(f, "{{ }}"),
+            Value::Set(_) if depth == 0 => write!(f, "{{ ... }}"),
+            Value::Set(attrs) => {
+                write!(f, "{{ ")?;
+                for (name, value) in attrs.iter() {
+                    if is_ident(name) {
+                        write!(f, "{}", name)?;
+                    } else {
+                        write_escaped(f, name)?;
+                    }
+                    write!(f, " = ")?;
+                    value.write_depth(f, depth - 1, path)?;
+                    write!(f, "; ")?;
+                }
+                write!(f, "}}")
+            }
+            Value::Func(_) | Value::PFunc(_) => write!(f, "<LAMBDA>"),
+            Value::Builtin(b) if b.is_partial() => write!(f, "<PRIMOP-APP>"),
+            Value::Builtin(_) => write!(f, "<PRIMOP>"),
+            Value::Thunk(thunk) => match &*thunk.state.borrow() {
+                ThunkState::Done(v) => v.write_depth(f, depth, path),
+                ThunkState::Pending(..) | ThunkState::Blackhole(_) => write!(f, "<CODE>"),
+            },
+        }
+    }
+}
+
+// Prints values the way `nix eval` does, with thunks that were never forced
+// shown as `<CODE>`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_depth(f, usize::MAX, &mut vec![])
+    }
+}
+
+// Floats are shown with 6 significant digits like C's `%g`, which is how
+// nix prints them: `0.333333`, `123457`, `1e+06`.
+fn format_float(fl: f64) -> String {
+    if !fl.is_finite() {
+        return if fl.is_nan() {
+            "nan".to_owned()
+        } else if fl > 0.0 {
+            "inf".to_owned()
+        } else {
+            "-inf".to_owned()
+        };
+    }
+    // Rounding to 6 digits first gives the exponent `%g` decides on.
+    let sci = format!("{:.5e}", fl);
+    let (mantissa, exp) = sci.split_once('e').expect("exponent is always written");
+    let exp: i32 = exp.parse().expect("exponent is an integer");
+    if (-4..6).contains(&exp) {
+        let fixed = format!("{:.*}", (5 - exp) as usize, fl);
+        return trim_fraction(&fixed).to_owned();
+    }
+    let sign = if exp < 0 { '-' } else { '+' };
+    format!("{}e{}{:02}", trim_fraction(mantissa), sign, exp.abs())
+}
+
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+// Attribute names that can't be written as an identifier are quoted.
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(ch) if ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '\'' | '-'))
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            '$' if chars.peek() == Some(&'{') => write!(f, "\\$")?,
+            _ => write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl From<&LiteralExpr> for Value {
+    fn from(l: &LiteralExpr) -> Self {
         match l {
-            LiteralExpr::Str(s) => Value::Str(s),
+            LiteralExpr::Str(s) => Value::Str(s.clone()),
             LiteralExpr::Int(i) => Value::Int(*i),
             LiteralExpr::Flo(f) => Value::Flo(*f),
-            LiteralExpr::Path(p) => Value::Path(p),
-            LiteralExpr::NixPath(p) => Value::NixPath(p),
+            LiteralExpr::Path(p) => Value::Path(p.clone()),
+            LiteralExpr::NixPath(p) => Value::NixPath(p.clone()),
             LiteralExpr::Bool(b) => Value::Bool(*b),
             LiteralExpr::Null() => Value::Null(),
         }
     }
 }
 
-impl<'a> From<i32> for Value<'a> {
-    fn from(i: i32) -> Self {
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
         Value::Int(i)
     }
 }
-impl<'a> From<f32> for Value<'a> {
-    fn from(f: f32) -> Self {
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
         Value::Flo(f)
     }
 }
-impl<'a> From<bool> for Value<'a> {
+impl From<bool> for Value {
     fn from(b: bool) -> Self {
         Value::Bool(b)
     }
 }
 
-impl<'a> TryFrom<IdentExpr<'a>> for Value<'a> {
+impl TryFrom<IdentExpr> for Value {
     type Error = Error;
-    fn try_from(v: IdentExpr<'a>) -> Result<Self> {
+    fn try_from(_v: IdentExpr) -> Result<Self> {
         unimplemented!();
     }
 }
 
-type Attributes<'a> = std::collections::BTreeMap<&'a str, Value<'a>>;
+type Attributes = std::collections::BTreeMap<Rc<str>, Value>;
 
+/// A scope. Environments are shared between the thunks and closures
+/// created in them, and bindings are added through a shared reference so
+/// that a scope can hold thunks referring back to itself.
 #[derive(Debug)]
-pub struct Env<'a> {
-    parent: Option<Box<Env<'a>>>,
-    children: Vec<Attributes<'a>>,
-    attrs: Attributes<'a>,
+pub struct Env {
+    parent: Option<Rc<Env>>,
+    // Scopes brought in by `with`, innermost last, along with the span of
+    // their expression. They stay thunks until a lookup falls through to them.
+    children: Vec<(Value, Span)>,
+    attrs: RefCell<Attributes>,
     allow_dep: bool,
 }
-impl<'a> Env<'a> {
-    pub fn new(maybe_parent: Option<Env<'a>>, allow_dep: bool) -> Self {
-        let children: Vec<Attributes<'a>> = vec![];
-        let attrs: Attributes<'a> = Attributes::new();
+impl Env {
+    pub fn new(maybe_parent: Option<Rc<Env>>, allow_dep: bool) -> Self {
+        let children: Vec<(Value, Span)> = vec![];
+        let attrs: Attributes = Attributes::new();
         Self {
-            parent: maybe_parent.map(Box::new),
+            parent: maybe_parent,
             children,
-            attrs,
+            attrs: RefCell::new(attrs),
             allow_dep,
         }
     }
-    pub fn set(&'a mut self, key: &'a str, val: Value<'a>) -> Result<&'a mut Self> {
-        if self.attrs.insert(key, val).is_some() {
+    pub fn set(&self, key: Rc<str>, val: Value) -> Result<&Self> {
+        if self.attrs.borrow_mut().insert(key, val).is_some() {
             bail!("duplicate attribute key in the environment")
         }
         Ok(self)
     }
-    pub fn has_indep(&self, key: &'a str) -> bool {
-        if let Some(v) = self.attrs.get(key) {
+    pub fn allows_dep(&self) -> bool {
+        self.allow_dep
+    }
+    pub fn has_indep(&self, key: &str) -> bool {
+        if let Some(v) = self.attrs.borrow().get(key) {
             if let Value::Dep(_) = v {
                 return false;
             }
@@ -91,33 +359,29 @@ impl<'a> Env<'a> {
         }
         false
     }
-    pub fn get(&'a self, id: &IdentExpr) -> Option<&'a Value> {
-        self.attrs.get(id.name)
+    pub fn get(&self, id: &IdentExpr) -> Option<Value> {
+        self.attrs.borrow().get(&id.name).cloned()
+    }
+    /// Looks `id` up in this scope and then in each parent in turn. The
+    /// scopes attached by `with` are not searched, see `with_scopes`.
+    pub fn resolve(&self, id: &IdentExpr) -> Option<Value> {
+        self.scopes().find_map(|env| env.get(id))
     }
-    pub fn resolve(&'a self, id: &IdentExpr) -> Option<&'a Value> {
-        if let Some(v) = self.get(id) {
-            return Some(v);
-        }
-
-        while let Some(p) = &self.parent {
-            if let Some(v) = p.get(id) {
-                return Some(v);
-            }
-        }
 
-        if let Some(v) = self.children.iter().rev().find_map(|t| t.get(id.name)) {
-            return Some(v);
-        }
+    /// The scopes attached by `with`, the innermost one first. They are
+    /// only searched for variables no enclosing scope binds.
+    pub fn with_scopes(&self) -> impl Iterator<Item = &(Value, Span)> {
+        self.scopes().flat_map(|env| env.children.iter().rev())
+    }
 
-        unimplemented!();
-        // let deps = BTreeSet::new();
-        // Some(Value::Dep(deps))
+    fn scopes(&self) -> impl Iterator<Item = &Env> {
+        std::iter::successors(Some(self), |env| env.parent.as_deref())
     }
 
-    pub fn attach(&'a mut self, attrs: Attributes<'a>) {
-        self.children.push(attrs);
+    pub fn attach(&mut self, scope: Value, span: Span) {
+        self.children.push((scope, span));
     }
-    pub fn dettach(&'a mut self) -> Option<Attributes> {
+    pub fn dettach(&mut self) -> Option<(Value, Span)> {
         self.children.pop()
     }
 }
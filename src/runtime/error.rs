@@ -0,0 +1,124 @@
+use crate::lexer::span::Span;
+use crate::parser::BinaryExprType;
+use crate::runtime::MAX_CALL_DEPTH;
+use std::fmt::Display;
+
+#[derive(Debug, PartialEq)]
+pub enum EvalErrorKind {
+    DivisionByZero,
+    IntegerOverflow,
+    InfiniteRecursion,
+    StackOverflow,
+    UndefinedVariable(String),
+    /// A selected attribute that doesn't exist, with the names in the set
+    /// that look like what was meant.
+    AttributeMissing {
+        name: String,
+        suggestions: Vec<String>,
+    },
+    DuplicateAttribute(String),
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    InvalidOperands {
+        op: BinaryExprType,
+        left: &'static str,
+        right: &'static str,
+    },
+    Incomparable {
+        left: &'static str,
+        right: &'static str,
+    },
+    CannotCoerce(&'static str),
+    /// A failed `assert`, with the condition's source text when known.
+    AssertionFailed(Option<String>),
+    /// Raised by `throw`.
+    Thrown(String),
+    /// Raised by `abort`.
+    Aborted(String),
+    MissingArgument(String),
+    UnexpectedArgument(String),
+    IndexOutOfBounds(i64),
+    /// A builtin that needs at least one element, given an empty list.
+    EmptyList(&'static str),
+    NegativeListSize(i64),
+}
+
+/// A failure while evaluating an expression, located at the offending
+/// node. Surfaced through `anyhow` and recoverable with `downcast_ref`.
+#[derive(Debug, PartialEq)]
+pub struct EvalError {
+    pub kind: EvalErrorKind,
+    pub span: Span,
+}
+impl EvalError {
+    pub fn new(kind: EvalErrorKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+impl Display for EvalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            EvalErrorKind::DivisionByZero => write!(f, "division by zero")?,
+            EvalErrorKind::IntegerOverflow => write!(f, "integer overflow")?,
+            EvalErrorKind::InfiniteRecursion => write!(f, "infinite recursion encountered")?,
+            EvalErrorKind::StackOverflow => write!(
+                f,
+                "stack overflow, max call depth of {} exceeded",
+                MAX_CALL_DEPTH
+            )?,
+            EvalErrorKind::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name)?,
+            EvalErrorKind::AttributeMissing { name, suggestions } => {
+                write!(f, "attribute '{}' missing", name)?;
+                match suggestions.as_slice() {
+                    [] => (),
+                    [only] => write!(f, " (did you mean '{}'?)", only)?,
+                    [rest @ .., last] => write!(
+                        f,
+                        " (did you mean one of {} or '{}'?)",
+                        rest.iter()
+                            .map(|s| format!("'{}'", s))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        last
+                    )?,
+                }
+            }
+            EvalErrorKind::DuplicateAttribute(name) => {
+                write!(f, "dynamic attribute '{}' already defined", name)?
+            }
+            EvalErrorKind::TypeMismatch { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)?
+            }
+            EvalErrorKind::InvalidOperands { op, left, right } => write!(
+                f,
+                "operator {} cannot be applied to {} and {}",
+                op, left, right
+            )?,
+            EvalErrorKind::Incomparable { left, right } => {
+                write!(f, "cannot compare {} with {}", left, right)?
+            }
+            EvalErrorKind::CannotCoerce(typ) => write!(f, "cannot coerce {} to a string", typ)?,
+            EvalErrorKind::AssertionFailed(Some(cond)) => write!(f, "assertion '{}' failed", cond)?,
+            EvalErrorKind::AssertionFailed(None) => write!(f, "assertion failed")?,
+            EvalErrorKind::Thrown(msg) => write!(f, "{}", msg)?,
+            EvalErrorKind::IndexOutOfBounds(i) => write!(f, "list index {} is out of bounds", i)?,
+            EvalErrorKind::EmptyList(name) => write!(f, "'{}' called on an empty list", name)?,
+            EvalErrorKind::NegativeListSize(n) => write!(f, "cannot create list of size {}", n)?,
+            EvalErrorKind::Aborted(msg) => write!(
+                f,
+                "evaluation aborted with the following error message: '{}'",
+                msg
+            )?,
+            EvalErrorKind::MissingArgument(name) => {
+                write!(f, "function called without required argument '{}'", name)?
+            }
+            EvalErrorKind::UnexpectedArgument(name) => {
+                write!(f, "function called with unexpected argument '{}'", name)?
+            }
+        }
+        write!(f, ", at {}", self.span)
+    }
+}
+impl std::error::Error for EvalError {}
@@ -1,48 +1,485 @@
-use anyhow::{anyhow, bail, Error, Result};
-use std::path::PathBuf;
+use anyhow::{anyhow, bail, Result};
+use std::cell::Cell;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 
+use crate::lexer::span::Span;
+use crate::lexer::{Lexer, Tokenizer};
 use crate::parser::{
-    Ast, BinaryExpr, BinaryExprType, Expr, IdentExpr, LiteralExpr, UnaryExpr, UnaryExprType,
+    Ast, AstParser, AttrName, BinaryExpr, BinaryExprType, Expr, ExprKind, Formals, HasAttrExpr,
+    IdentExpr, InterpolatedExpr, LetExpr, Parser, SelectExpr, SetExpr, SetFormals, StrPart,
+    UnaryExpr, UnaryExprType, WithExpr,
 };
-use crate::runtime::env::{Env, Value};
+use crate::runtime::builtins::global_env;
+use crate::runtime::env::{Closure, Env, Thunk, ThunkState, Value};
+use crate::runtime::error::{EvalError, EvalErrorKind};
+use crate::stack::grow_stack;
 
 #[derive(Debug)]
-pub struct Interpreter<'a, 'b> {
-    ast: &'a Ast<'a>,
-    env: Env<'b>,
+pub struct Interpreter {
+    ast: Ast,
+    env: Rc<Env>,
+    // Number of function calls and nested comparisons currently being
+    // evaluated.
+    call_depth: Cell<usize>,
 }
-impl<'a, 'b> Interpreter<'a, 'b> {
-    fn evaluate(&'a self, e: &'a Expr<'a>) -> Result<Value<'a>> {
-        match e {
-            Expr::Literal(l) => Ok(Value::from(l)),
-            Expr::Unary(u) => match self.evaluate(&u.right) {
-                Ok(v) => {
-                    if let Value::Dep(_) = v {
-                        return Ok(v);
+
+/// Deepest nesting of function calls before evaluation gives up, the same
+/// default as nix's `max-call-depth`. The native stack is grown as needed,
+/// so the limit is reached the same way on any thread.
+pub const MAX_CALL_DEPTH: usize = 10000;
+
+impl Interpreter {
+    fn evaluate(&self, e: &Expr, env: &Rc<Env>) -> Result<Value> {
+        grow_stack(|| self.eval_expr(e, env))
+    }
+
+    fn eval_expr(&self, e: &Expr, env: &Rc<Env>) -> Result<Value> {
+        match &*e.kind {
+            ExprKind::Literal(l) => Ok(Value::from(l)),
+            ExprKind::Interpolated(s) => self.eval_interpolated(s, env),
+            ExprKind::Ident(id) | ExprKind::Inherit(id) => match env.resolve(id) {
+                Some(v) => self.force(v),
+                None => self.resolve_with(id, e.span, env),
+            },
+            ExprKind::Let(l) => self.eval_let(l, env),
+            ExprKind::Unary(u) => self.eval_unary(u, e.span, env),
+            ExprKind::Binary(b) => self.eval_binary(b, env),
+            ExprKind::Set(s) => self.eval_set(s, env),
+            ExprKind::List(l) => Ok(Value::List(Rc::new(
+                l.elems.iter().map(|expr| self.delay(expr, env)).collect(),
+            ))),
+            ExprKind::Select(s) => self.eval_select(s, e.span, env),
+            ExprKind::HasAttr(h) => self.eval_has_attr(h, env),
+            ExprKind::If(i) => {
+                if self.eval_bool(&i.cond, env)? {
+                    self.evaluate(&i.truthy, env)
+                } else {
+                    self.evaluate(&i.falsy, env)
+                }
+            }
+            ExprKind::With(w) => self.eval_with(w, env),
+            ExprKind::Assert(a) => {
+                if !self.eval_bool(&a.cond, env)? {
+                    let cond = a.cond_text.as_deref().map(str::to_owned);
+                    return Err(
+                        EvalError::new(EvalErrorKind::AssertionFailed(cond), a.cond.span).into(),
+                    );
+                }
+                self.evaluate(&a.body, env)
+            }
+            ExprKind::Lambda(l) => match l.formals {
+                Formals::Ident(_) => Ok(Value::Func(Closure::new(l.clone(), env.clone()))),
+                Formals::Set(_) => Ok(Value::PFunc(Closure::new(l.clone(), env.clone()))),
+            },
+            ExprKind::Apply(a) => {
+                let func = self.evaluate(&a.func, env)?;
+                self.apply(func, self.delay(&a.arg, env), e.span)
+            }
+            _ => bail!(
+                "evaluating this expression is not supported yet, at {}",
+                e.span
+            ),
+        }
+    }
+
+    fn eval_interpolated(&self, s: &InterpolatedExpr, env: &Rc<Env>) -> Result<Value> {
+        let mut out = String::new();
+        for part in s.parts.iter() {
+            match part {
+                StrPart::Literal(lit) => out.push_str(lit),
+                StrPart::Expr(expr) => {
+                    let v = self.evaluate(expr, env)?;
+                    out.push_str(&self.coerce_to_string(v, expr.span)?);
+                }
+            }
+        }
+        Ok(Value::Str(Rc::from(out)))
+    }
+
+    // Bindings live in a new scope and are evaluated in it, so they can
+    // refer to each other.
+    fn eval_let(&self, l: &LetExpr, env: &Rc<Env>) -> Result<Value> {
+        let scope = Rc::new(Env::new(Some(env.clone()), false));
+        let inner = self.inherit_scope(&l.inherit_from, &scope)?;
+        for (ident, expr) in l.bindings.iter() {
+            scope.set(ident.name.clone(), self.delay_binding(expr, env, &inner))?;
+        }
+        self.evaluate(&l.body, &scope)
+    }
+
+    fn eval_unary(&self, u: &UnaryExpr, span: Span, env: &Rc<Env>) -> Result<Value> {
+        let v = self.evaluate(&u.right, env)?;
+        if let Value::Dep(_) = v {
+            return Ok(v);
+        }
+        match u.typ {
+            UnaryExprType::ArithmNegation() => match v {
+                Value::Int(i) => match i.checked_neg() {
+                    Some(neg) => Ok(Value::from(neg)),
+                    None => Err(EvalError::new(EvalErrorKind::IntegerOverflow, span).into()),
+                },
+                Value::Flo(f) => Ok(Value::from(-f)),
+                v => Err(EvalError::new(
+                    EvalErrorKind::TypeMismatch {
+                        expected: "number",
+                        found: v.type_name(),
+                    },
+                    u.right.span,
+                )
+                .into()),
+            },
+            UnaryExprType::LogicalNegation() => match v {
+                Value::Bool(b) => Ok(Value::from(!b)),
+                v => Err(EvalError::new(
+                    EvalErrorKind::TypeMismatch {
+                        expected: "bool",
+                        found: v.type_name(),
+                    },
+                    u.right.span,
+                )
+                .into()),
+            },
+        }
+    }
+
+    // The attributes of a recursive set are evaluated in a scope holding the
+    // very same thunks.
+    fn eval_set(&self, s: &SetExpr, env: &Rc<Env>) -> Result<Value> {
+        if s.rec {
+            let scope = Rc::new(Env::new(Some(env.clone()), false));
+            let inner = self.inherit_scope(&s.inherit_from, &scope)?;
+            let mut attrs = BTreeMap::new();
+            for (name, expr) in s.elems.iter() {
+                let v = self.delay_binding(expr, env, &inner);
+                scope.set(name.clone(), v.clone())?;
+                attrs.insert(name.clone(), v);
+            }
+            self.eval_dynamic_attrs(s, &scope, &mut attrs)?;
+            return Ok(Value::Set(Rc::new(attrs)));
+        }
+
+        let inner = self.inherit_scope(&s.inherit_from, env)?;
+        let mut attrs: BTreeMap<_, _> = s
+            .elems
+            .iter()
+            .map(|(name, expr)| (name.clone(), self.delay(expr, &inner)))
+            .collect();
+        self.eval_dynamic_attrs(s, env, &mut attrs)?;
+        Ok(Value::Set(Rc::new(attrs)))
+    }
+
+    fn eval_has_attr(&self, h: &HasAttrExpr, env: &Rc<Env>) -> Result<Value> {
+        let mut v = self.evaluate(&h.set, env)?;
+        for name in h.path.iter() {
+            let name = self.eval_attr_name(name, env)?;
+            v = match v {
+                Value::Set(attrs) => match attrs.get(&name) {
+                    Some(attr) => self.force(attr.clone())?,
+                    None => return Ok(Value::Bool(false)),
+                },
+                _ => return Ok(Value::Bool(false)),
+            };
+        }
+        Ok(Value::Bool(true))
+    }
+
+    // The attributes of the set are only looked at for names that aren't
+    // bound lexically.
+    // The scope is only evaluated once a variable isn't bound otherwise, so
+    // `with throw "x"; 1` is 1.
+    fn eval_with(&self, w: &WithExpr, env: &Rc<Env>) -> Result<Value> {
+        let mut scope = Env::new(Some(env.clone()), false);
+        scope.attach(self.delay(&w.scope, env), w.scope.span);
+        self.evaluate(&w.expr, &Rc::new(scope))
+    }
+
+    // Looks up a variable no enclosing scope binds in the sets brought in
+    // by `with`, forcing them one at a time from the innermost.
+    fn resolve_with(&self, id: &IdentExpr, span: Span, env: &Rc<Env>) -> Result<Value> {
+        for (scope, scope_span) in env.with_scopes() {
+            match self.force(scope.clone())? {
+                Value::Set(attrs) => {
+                    if let Some(v) = attrs.get(&id.name) {
+                        return self.force(v.clone());
                     }
-                    match u.typ {
-                        UnaryExprType::ArithmNegation() => match u.right {
-                            Expr::Literal(LiteralExpr::Int(i)) => Ok(Value::from(-i)),
-                            Expr::Literal(LiteralExpr::Flo(f)) => Ok(Value::from(-f)),
-                            _ => bail!("Arithmetic negation operator - can only be applied on number types"),
+                }
+                v => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::TypeMismatch {
+                            expected: "set",
+                            found: v.type_name(),
                         },
-                        UnaryExprType::LogicalNegation() => match u.right {
-                            Expr::Literal(LiteralExpr::Bool(b)) => Ok(Value::from(b)),
-                            _ => bail!("Logical negation ! operator can only be applied to a boolean"),
-                        }
-                    }
+                        *scope_span,
+                    )
+                    .into())
                 }
-                Err(e) => Err(e),
+            }
+        }
+        Err(EvalError::new(EvalErrorKind::UndefinedVariable(id.name.to_string()), span).into())
+    }
+
+    /// Calls `func` with `arg`. Besides functions, sets with a `__functor`
+    /// attribute can be called: the functor is given the set itself first.
+    pub fn apply(&self, func: Value, arg: Value, span: Span) -> Result<Value> {
+        self.nested(span, || self.call(func, arg, span))
+    }
+
+    // Runs `f` one level deeper, failing once `MAX_CALL_DEPTH` is reached.
+    fn nested<T>(&self, span: Span, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        let depth = self.call_depth.get();
+        if depth >= MAX_CALL_DEPTH {
+            return Err(EvalError::new(EvalErrorKind::StackOverflow, span).into());
+        }
+        self.call_depth.set(depth + 1);
+        let res = grow_stack(f);
+        self.call_depth.set(depth);
+        res
+    }
+
+    fn call(&self, func: Value, arg: Value, span: Span) -> Result<Value> {
+        match self.force(func)? {
+            Value::Func(c) => {
+                let scope = Rc::new(Env::new(Some(c.env.clone()), false));
+                if let Formals::Ident(id) = &c.lambda.formals {
+                    scope.set(id.name.clone(), arg)?;
+                }
+                self.evaluate(&c.lambda.body, &scope)
+            }
+            Value::PFunc(c) => {
+                let scope = Rc::new(Env::new(Some(c.env.clone()), false));
+                if let Formals::Set(formals) = &c.lambda.formals {
+                    self.bind_formals(formals, arg, &scope, span)?;
+                }
+                self.evaluate(&c.lambda.body, &scope)
+            }
+            Value::Builtin(b) => b.apply(self, arg, span),
+            Value::Set(attrs) if attrs.contains_key("__functor") => {
+                let functor = attrs["__functor"].clone();
+                let func = self.apply(functor, Value::Set(attrs), span)?;
+                self.apply(func, arg, span)
+            }
+            v => Err(EvalError::new(
+                EvalErrorKind::TypeMismatch {
+                    expected: "function",
+                    found: v.type_name(),
+                },
+                span,
+            )
+            .into()),
+        }
+    }
+
+    // Binds the attributes of `arg` to the formals of a set pattern.
+    // Defaults are evaluated in `scope`, so they can refer to the other
+    // formals.
+    fn bind_formals(
+        &self,
+        formals: &SetFormals,
+        arg: Value,
+        scope: &Rc<Env>,
+        span: Span,
+    ) -> Result<()> {
+        let attrs = match self.force(arg)? {
+            Value::Set(attrs) => attrs,
+            v => {
+                return Err(EvalError::new(
+                    EvalErrorKind::TypeMismatch {
+                        expected: "set",
+                        found: v.type_name(),
+                    },
+                    span,
+                )
+                .into())
+            }
+        };
+        if !formals.ellipsis {
+            let unexpected = attrs
+                .keys()
+                .find(|name| !formals.formals.iter().any(|f| f.name.name == **name));
+            if let Some(name) = unexpected {
+                return Err(EvalError::new(
+                    EvalErrorKind::UnexpectedArgument(name.to_string()),
+                    span,
+                )
+                .into());
+            }
+        }
+        for formal in formals.formals.iter() {
+            let v = match (attrs.get(&formal.name.name), &formal.default) {
+                (Some(v), _) => v.clone(),
+                (None, Some(default)) => self.delay(default, scope),
+                (None, None) => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::MissingArgument(formal.name.name.to_string()),
+                        span,
+                    )
+                    .into())
+                }
+            };
+            scope.set(formal.name.name.clone(), v)?;
+        }
+        if let Some(bind) = &formals.bind {
+            scope.set(bind.name.clone(), Value::Set(attrs))?;
+        }
+        Ok(())
+    }
+
+    // Follows the attribute path of `s` one name at a time. The default, if
+    // any, is used both when a name is missing and when something along the
+    // way isn't a set.
+    fn eval_select(&self, s: &SelectExpr, span: Span, env: &Rc<Env>) -> Result<Value> {
+        let mut v = self.evaluate(&s.set, env)?;
+        for name in s.path.iter() {
+            let name = self.eval_attr_name(name, env)?;
+            let attr = match &v {
+                Value::Set(attrs) => attrs.get(&name),
+                _ if s.default.is_some() => None,
+                v => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::TypeMismatch {
+                            expected: "set",
+                            found: v.type_name(),
+                        },
+                        s.set.span,
+                    )
+                    .into())
+                }
+            };
+            v = match (attr, &s.default) {
+                (Some(attr), _) => self.force(attr.clone())?,
+                (None, Some(default)) => return self.evaluate(default, env),
+                (None, None) => {
+                    let suggestions = match &v {
+                        Value::Set(attrs) => similar_names(&name, attrs.keys()),
+                        _ => vec![],
+                    };
+                    return Err(EvalError::new(
+                        EvalErrorKind::AttributeMissing {
+                            name: name.to_string(),
+                            suggestions,
+                        },
+                        span,
+                    )
+                    .into());
+                }
+            };
+        }
+        Ok(v)
+    }
+
+    fn eval_bool(&self, e: &Expr, env: &Rc<Env>) -> Result<bool> {
+        match self.evaluate(e, env)? {
+            Value::Bool(b) => Ok(b),
+            v => Err(EvalError::new(
+                EvalErrorKind::TypeMismatch {
+                    expected: "bool",
+                    found: v.type_name(),
+                },
+                e.span,
+            )
+            .into()),
+        }
+    }
+
+    // Resolves one segment of an attribute path to the name it stands for.
+    fn eval_attr_name(&self, name: &AttrName, env: &Rc<Env>) -> Result<Rc<str>> {
+        match name {
+            AttrName::Static(name) => Ok(name.clone()),
+            AttrName::Dynamic(e) => match self.evaluate(e, env)? {
+                Value::Str(name) => Ok(name),
+                v => Err(EvalError::new(
+                    EvalErrorKind::TypeMismatch {
+                        expected: "string",
+                        found: v.type_name(),
+                    },
+                    e.span,
+                )
+                .into()),
             },
-            Expr::Binary(b) => self.eval_binary(b),
-            _ => unimplemented!(),
         }
     }
-    fn eval_binary(&'a self, b: &'a BinaryExpr) -> Result<Value<'a>> {
-        match self.evaluate(&b.left) {
+
+    // Adds the `${name} = value;` attributes of `s` to `attrs`. Their names
+    // are forced right away, their values stay lazy. Attributes named `null`
+    // are left out.
+    fn eval_dynamic_attrs(
+        &self,
+        s: &SetExpr,
+        env: &Rc<Env>,
+        attrs: &mut BTreeMap<Rc<str>, Value>,
+    ) -> Result<()> {
+        for (name_expr, expr) in s.dynamic.iter() {
+            let name = match self.evaluate(name_expr, env)? {
+                Value::Str(name) => name,
+                Value::Null() => continue,
+                v => {
+                    return Err(EvalError::new(
+                        EvalErrorKind::TypeMismatch {
+                            expected: "string",
+                            found: v.type_name(),
+                        },
+                        name_expr.span,
+                    )
+                    .into())
+                }
+            };
+            if attrs.insert(name.clone(), self.delay(expr, env)).is_some() {
+                return Err(EvalError::new(
+                    EvalErrorKind::DuplicateAttribute(name.to_string()),
+                    name_expr.span,
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    // Converts an interpolated value to a string. Only strings, paths and
+    // sets that describe how to print themselves can be interpolated.
+    fn coerce_to_string(&self, v: Value, span: Span) -> Result<Rc<str>> {
+        self.coerce(v, span, false)
+    }
+
+    /// Converts `v` to a string the way `toString` does. Unlike
+    /// interpolation, this also accepts numbers, booleans, null and lists.
+    pub fn coerce_more_to_string(&self, v: Value, span: Span) -> Result<Rc<str>> {
+        self.coerce(v, span, true)
+    }
+
+    fn coerce(&self, v: Value, span: Span, coerce_more: bool) -> Result<Rc<str>> {
+        match self.force(v)? {
+            Value::Str(s) => Ok(s),
+            Value::Path(p) | Value::NixPath(p) => Ok(p),
+            Value::Set(attrs) if attrs.contains_key("__toString") => {
+                let to_string = attrs["__toString"].clone();
+                let s = self.apply(to_string, Value::Set(attrs), span)?;
+                self.coerce(s, span, coerce_more)
+            }
+            Value::Set(attrs) if attrs.contains_key("outPath") => {
+                self.coerce(attrs["outPath"].clone(), span, coerce_more)
+            }
+            Value::Int(i) if coerce_more => Ok(Rc::from(i.to_string())),
+            Value::Flo(f) if coerce_more => Ok(Rc::from(format!("{:.6}", f))),
+            Value::Bool(true) if coerce_more => Ok(Rc::from("1")),
+            Value::Bool(false) | Value::Null() if coerce_more => Ok(Rc::from("")),
+            Value::List(elems) if coerce_more => {
+                let strs = elems
+                    .iter()
+                    .map(|e| self.coerce(e.clone(), span, true))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Rc::from(strs.join(" ")))
+            }
+            v => Err(EvalError::new(EvalErrorKind::CannotCoerce(v.type_name()), span).into()),
+        }
+    }
+
+    fn eval_binary(&self, b: &BinaryExpr, env: &Rc<Env>) -> Result<Value> {
+        match self.evaluate(&b.left, env) {
             Ok(left) => {
                 if let Value::Dep(_) = left {
-                    if let Ok(Value::Dep(_)) = self.evaluate(&b.right) {
+                    if let Ok(Value::Dep(_)) = self.evaluate(&b.right, env) {
                         unimplemented!();
                     }
                     return Ok(left);
@@ -63,37 +500,342 @@ impl<'a, 'b> Interpreter<'a, 'b> {
                                 }
                             }
 
-                            if let Ok(Value::Bool(rb)) = self.evaluate(&b.right) {
-                                return Ok(Value::Bool(eval_logical(b.typ, lb, rb)));
-                            }
-                            bail!(
-                                "Expecting right operand to be a boolean for opearator {:?}",
-                                b.right
-                            )
+                            return match self.evaluate(&b.right, env)? {
+                                Value::Bool(rb) => Ok(Value::Bool(eval_logical(b.typ, lb, rb))),
+                                v => Err(EvalError::new(
+                                    EvalErrorKind::TypeMismatch {
+                                        expected: "bool",
+                                        found: v.type_name(),
+                                    },
+                                    b.right.span,
+                                )
+                                .into()),
+                            };
                         }
-                        bail!("Expecting left operand to be a boolean for operator {:?}, but got {:?}", b.typ, left);
+                        Err(EvalError::new(
+                            EvalErrorKind::TypeMismatch {
+                                expected: "bool",
+                                found: left.type_name(),
+                            },
+                            b.left.span,
+                        )
+                        .into())
                     }
                     BinaryExprType::Equals() => {
-                        let right = self.evaluate(&b.right)?;
-                        Ok(Value::Bool(eval_equal(&left, &right)))
+                        let right = self.evaluate(&b.right, env)?;
+                        Ok(Value::Bool(self.eval_equal(
+                            &left,
+                            &right,
+                            b.left.span.to(b.right.span),
+                        )?))
                     }
                     BinaryExprType::NotEquals() => {
-                        let right = self.evaluate(&b.right)?;
-                        Ok(Value::Bool(!eval_equal(&left, &right)))
+                        let right = self.evaluate(&b.right, env)?;
+                        Ok(Value::Bool(!self.eval_equal(
+                            &left,
+                            &right,
+                            b.left.span.to(b.right.span),
+                        )?))
+                    }
+                    BinaryExprType::Less()
+                    | BinaryExprType::More()
+                    | BinaryExprType::LessOrEquals()
+                    | BinaryExprType::MoreOrEquals() => {
+                        let right = self.evaluate(&b.right, env)?;
+                        let ord = self.eval_compare(&left, &right, b.left.span.to(b.right.span))?;
+                        let res = match b.typ {
+                            BinaryExprType::Less() => ord == Some(Ordering::Less),
+                            BinaryExprType::More() => ord == Some(Ordering::Greater),
+                            BinaryExprType::LessOrEquals() => {
+                                matches!(ord, Some(Ordering::Less | Ordering::Equal))
+                            }
+                            _ => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                        };
+                        Ok(Value::Bool(res))
                     }
-                    _ => unimplemented!(),
+                    BinaryExprType::Add()
+                    | BinaryExprType::Sub()
+                    | BinaryExprType::Mult()
+                    | BinaryExprType::Div() => {
+                        let right = self.evaluate(&b.right, env)?;
+                        eval_arithm(b.typ, &left, &right).map_err(|kind| {
+                            EvalError::new(kind, b.left.span.to(b.right.span)).into()
+                        })
+                    }
+                    BinaryExprType::Concat() => match (left, self.evaluate(&b.right, env)?) {
+                        (Value::List(l), Value::List(r)) => Ok(Value::List(Rc::new(
+                            l.iter().chain(r.iter()).cloned().collect(),
+                        ))),
+                        (l, r) => Err(invalid_operands(b, &l, &r)),
+                    },
+                    // Attributes of the right operand win over the left one.
+                    BinaryExprType::Update() => match (left, self.evaluate(&b.right, env)?) {
+                        (Value::Set(l), Value::Set(r)) => {
+                            let mut attrs = (*l).clone();
+                            attrs.extend(r.iter().map(|(name, v)| (name.clone(), v.clone())));
+                            Ok(Value::Set(Rc::new(attrs)))
+                        }
+                        (l, r) => Err(invalid_operands(b, &l, &r)),
+                    },
                 }
             }
             Err(e) => Err(e),
         }
     }
-    pub fn interpret(&'a mut self) -> Result<Value<'a>> {
-        self.evaluate(self.ast)
+    // Defers evaluation of `e` until the value is needed. Literals are
+    // cheaper to build than a thunk, so they are evaluated right away.
+    fn delay(&self, e: &Expr, env: &Rc<Env>) -> Value {
+        match &*e.kind {
+            ExprKind::Literal(l) => Value::from(l),
+            _ => Value::Thunk(Rc::new(Thunk::new(e.clone(), env.clone()))),
+        }
+    }
+
+    // Inherited bindings skip the scope they are defined in, so that
+    // `let inherit x; in` refers to the outer `x` rather than to itself.
+    fn delay_binding(&self, e: &Expr, outer: &Rc<Env>, scope: &Rc<Env>) -> Value {
+        match &*e.kind {
+            ExprKind::Inherit(_) => self.delay(e, outer),
+            _ => self.delay(e, scope),
+        }
+    }
+
+    // Binds the `inherit (src)` sources of a set or let block to a single
+    // thunk each, in a scope the block's attributes are delayed in.
+    fn inherit_scope(&self, sources: &[(Rc<str>, Expr)], env: &Rc<Env>) -> Result<Rc<Env>> {
+        if sources.is_empty() {
+            return Ok(env.clone());
+        }
+        let scope = Env::new(Some(env.clone()), false);
+        for (name, src) in sources.iter() {
+            scope.set(name.clone(), self.delay(src, env))?;
+        }
+        Ok(Rc::new(scope))
+    }
+
+    /// Evaluates `v` if it is a thunk, caching the result in it.
+    pub fn force(&self, v: Value) -> Result<Value> {
+        let thunk = match v {
+            Value::Thunk(thunk) => thunk,
+            v => return Ok(v),
+        };
+        let expr = match &*thunk.state.borrow() {
+            ThunkState::Done(v) => return Ok(v.clone()),
+            ThunkState::Blackhole(expr) => {
+                return Err(EvalError::new(EvalErrorKind::InfiniteRecursion, expr.span).into())
+            }
+            ThunkState::Pending(expr, _) => expr.clone(),
+        };
+        let env = match thunk.state.replace(ThunkState::Blackhole(expr.clone())) {
+            ThunkState::Pending(_, env) => env,
+            _ => unreachable!("thunk state changed while checking it"),
+        };
+        let v = self.evaluate(&expr, &env);
+        *thunk.state.borrow_mut() = match &v {
+            Ok(v) => ThunkState::Done(v.clone()),
+            // Leave the thunk to be retried (and fail again) if forced later.
+            Err(_) => ThunkState::Pending(expr, env),
+        };
+        v
+    }
+
+    /// Forces `v` and every element or attribute directly inside it.
+    pub fn force_shallow(&self, v: Value) -> Result<Value> {
+        let v = self.force(v)?;
+        match &v {
+            Value::List(elems) => elems
+                .iter()
+                .try_for_each(|e| self.force(e.clone()).map(drop))?,
+            Value::Set(attrs) => attrs
+                .values()
+                .try_for_each(|e| self.force(e.clone()).map(drop))?,
+            _ => (),
+        }
+        Ok(v)
+    }
+
+    /// Forces `v` and everything nested inside it.
+    pub fn force_deep(&self, v: Value) -> Result<Value> {
+        self.force_deep_in(v, &mut vec![])
+    }
+
+    // `path` holds the sets and lists being forced, so that a value nested
+    // inside itself is only walked once.
+    fn force_deep_in(&self, v: Value, path: &mut Vec<*const ()>) -> Result<Value> {
+        grow_stack(|| self.force_deep_value(v, path))
+    }
+
+    fn force_deep_value(&self, v: Value, path: &mut Vec<*const ()>) -> Result<Value> {
+        let v = self.force(v)?;
+        let ptr = match v.container_ptr() {
+            Some(ptr) if !path.contains(&ptr) => ptr,
+            _ => return Ok(v),
+        };
+        path.push(ptr);
+        match &v {
+            Value::List(elems) => elems
+                .iter()
+                .try_for_each(|e| self.force_deep_in(e.clone(), path).map(drop))?,
+            Value::Set(attrs) => attrs
+                .values()
+                .try_for_each(|e| self.force_deep_in(e.clone(), path).map(drop))?,
+            _ => (),
+        }
+        path.pop();
+        Ok(v)
+    }
+
+    // Functions never compare equal, numbers compare across int and float,
+    // lists and sets compare element by element. A list or set is always
+    // equal to itself, which also keeps values that contain themselves from
+    // being walked forever.
+    pub(crate) fn eval_equal(&self, l: &Value, r: &Value, span: Span) -> Result<bool> {
+        let (l, r) = (self.force(l.clone())?, self.force(r.clone())?);
+        match (&l, &r) {
+            (Value::List(l), Value::List(r)) if Rc::ptr_eq(l, r) => Ok(true),
+            (Value::Set(l), Value::Set(r)) if Rc::ptr_eq(l, r) => Ok(true),
+            (Value::Func(_) | Value::PFunc(_) | Value::Builtin(_), _)
+            | (_, Value::Func(_) | Value::PFunc(_) | Value::Builtin(_)) => Ok(false),
+            (Value::Int(_), Value::Flo(_)) | (Value::Flo(_), Value::Int(_)) => {
+                Ok(as_flo(&l) == as_flo(&r))
+            }
+            (Value::List(l), Value::List(r)) => {
+                if l.len() != r.len() {
+                    return Ok(false);
+                }
+                for (l, r) in l.iter().zip(r.iter()) {
+                    if !self.nested(span, || self.eval_equal(l, r, span))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            (Value::Set(l), Value::Set(r)) => {
+                if !l.keys().eq(r.keys()) {
+                    return Ok(false);
+                }
+                for (l, r) in l.values().zip(r.values()) {
+                    if !self.nested(span, || self.eval_equal(l, r, span))? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            _ => Ok(l == r),
+        }
+    }
+
+    // Numbers compare across int and float, strings and paths
+    // lexicographically and lists element by element. `None` means the
+    // values are comparable but unordered, as with NaN.
+    fn eval_compare(&self, l: &Value, r: &Value, span: Span) -> Result<Option<Ordering>> {
+        let (l, r) = (self.force(l.clone())?, self.force(r.clone())?);
+        match (&l, &r) {
+            (Value::Int(l), Value::Int(r)) => Ok(Some(l.cmp(r))),
+            (Value::Int(_) | Value::Flo(_), Value::Int(_) | Value::Flo(_)) => {
+                Ok(as_flo(&l).partial_cmp(&as_flo(&r)))
+            }
+            (Value::Str(l), Value::Str(r)) => Ok(Some(l.cmp(r))),
+            (Value::Path(l) | Value::NixPath(l), Value::Path(r) | Value::NixPath(r)) => {
+                Ok(Some(l.cmp(r)))
+            }
+            (Value::List(l), Value::List(r)) => {
+                for (l, r) in l.iter().zip(r.iter()) {
+                    match self.nested(span, || self.eval_compare(l, r, span))? {
+                        Some(Ordering::Equal) => continue,
+                        ord => return Ok(ord),
+                    }
+                }
+                Ok(Some(l.len().cmp(&r.len())))
+            }
+            _ => Err(EvalError::new(
+                EvalErrorKind::Incomparable {
+                    left: l.type_name(),
+                    right: r.type_name(),
+                },
+                span,
+            )
+            .into()),
+        }
+    }
+
+    pub fn interpret(&mut self) -> Result<Value> {
+        self.evaluate(&self.ast, &self.env)
+    }
+    pub fn new(ast: &Ast) -> Self {
+        Self {
+            ast: ast.clone(),
+            env: global_env(),
+            call_depth: Cell::new(0),
+        }
+    }
+    /// Evaluates `ast` on top of an existing environment, e.g. the bindings
+    /// accumulated by the REPL.
+    pub fn with_env(ast: &Ast, env: Rc<Env>) -> Self {
+        Self {
+            ast: ast.clone(),
+            env,
+            call_depth: Cell::new(0),
+        }
     }
-    pub fn new(ast: &'a Ast) -> Self {
-        let env = Env::new(None, false);
-        Self { ast, env }
+}
+
+/// Lexes, parses and fully evaluates `source`. The result does not borrow
+/// from the source, so it can outlive it.
+pub fn eval(source: &str) -> Result<Value> {
+    let mut lexer = Lexer::new(source);
+    let toks = lexer.tokenize().map_err(|errors| {
+        anyhow!(errors
+            .iter()
+            .map(|err| err.to_string())
+            .collect::<Vec<_>>()
+            .join("\n"))
+    })?;
+    let ast = AstParser::new(toks)
+        .with_source(source)
+        .parse()
+        .map_err(|parse_errors| {
+            anyhow!(parse_errors
+                .errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"))
+        })?;
+    let mut interpreter = Interpreter::new(&ast);
+    let value = interpreter.interpret()?;
+    interpreter.force_deep(value)
+}
+
+// Up to three of `names` that are a small typo away from `name`, closest
+// first. Names too short to have a typo in them get no suggestions.
+fn similar_names<'n>(name: &str, names: impl Iterator<Item = &'n Rc<str>>) -> Vec<String> {
+    let max_distance = name.chars().count().saturating_sub(1).min(2);
+    let mut similar: Vec<(usize, &Rc<str>)> = names
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        .collect();
+    similar.sort();
+    similar
+        .into_iter()
+        .take(3)
+        .map(|(_, candidate)| candidate.to_string())
+        .collect()
+}
+
+// Levenshtein distance between `a` and `b`, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            curr.push(substitution.min(prev[j + 1] + 1).min(curr[j] + 1));
+        }
+        prev = curr;
     }
+    prev[b.len()]
 }
 
 fn eval_logical(e: BinaryExprType, l: bool, r: bool) -> bool {
@@ -105,12 +847,67 @@ fn eval_logical(e: BinaryExprType, l: bool, r: bool) -> bool {
     }
 }
 
-// FIX: impl deep equality
-fn eval_equal(l: &Value, r: &Value) -> bool {
+// Integers stay integers (with checked overflow and truncating division)
+// unless either side is a float, in which case both are promoted. `+` also
+// concatenates strings and paths, keeping the type of the left operand.
+fn eval_arithm(
+    op: BinaryExprType,
+    l: &Value,
+    r: &Value,
+) -> std::result::Result<Value, EvalErrorKind> {
     match (l, r) {
-        (Value::Func(), _) | (Value::PFunc(), _) | (_, Value::Func()) | (_, Value::PFunc()) => {
-            false
+        (Value::Str(l), Value::Str(r) | Value::Path(r)) if op == BinaryExprType::Add() => {
+            Ok(Value::Str(Rc::from(format!("{}{}", l, r))))
+        }
+        (Value::Path(l), Value::Path(r) | Value::Str(r)) if op == BinaryExprType::Add() => {
+            Ok(Value::Path(Rc::from(format!("{}{}", l, r))))
+        }
+        (Value::Int(l), Value::Int(r)) => {
+            let res = match op {
+                BinaryExprType::Add() => l.checked_add(*r),
+                BinaryExprType::Sub() => l.checked_sub(*r),
+                BinaryExprType::Mult() => l.checked_mul(*r),
+                BinaryExprType::Div() if *r == 0 => return Err(EvalErrorKind::DivisionByZero),
+                BinaryExprType::Div() => l.checked_div(*r),
+                _ => unreachable!("{} is not an arithmetic operator", op),
+            };
+            res.map(Value::Int).ok_or(EvalErrorKind::IntegerOverflow)
+        }
+        (Value::Int(_) | Value::Flo(_), Value::Int(_) | Value::Flo(_)) => {
+            let (l, r) = (as_flo(l), as_flo(r));
+            match op {
+                BinaryExprType::Add() => Ok(Value::Flo(l + r)),
+                BinaryExprType::Sub() => Ok(Value::Flo(l - r)),
+                BinaryExprType::Mult() => Ok(Value::Flo(l * r)),
+                BinaryExprType::Div() if r == 0.0 => Err(EvalErrorKind::DivisionByZero),
+                BinaryExprType::Div() => Ok(Value::Flo(l / r)),
+                _ => unreachable!("{} is not an arithmetic operator", op),
+            }
         }
-        _ => l == r,
+        _ => Err(EvalErrorKind::InvalidOperands {
+            op,
+            left: l.type_name(),
+            right: r.type_name(),
+        }),
+    }
+}
+
+fn invalid_operands(b: &BinaryExpr, l: &Value, r: &Value) -> anyhow::Error {
+    EvalError::new(
+        EvalErrorKind::InvalidOperands {
+            op: b.typ,
+            left: l.type_name(),
+            right: r.type_name(),
+        },
+        b.left.span.to(b.right.span),
+    )
+    .into()
+}
+
+fn as_flo(v: &Value) -> f64 {
+    match v {
+        Value::Int(i) => *i as f64,
+        Value::Flo(f) => *f,
+        _ => unreachable!("{:?} is not a number", v),
     }
 }
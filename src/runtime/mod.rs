@@ -1,5 +1,11 @@
+mod builtins;
 mod env;
+mod error;
 mod graph;
 mod interpreter;
+mod tests_interpreter;
 
+pub use builtins::*;
+pub use env::*;
+pub use error::*;
 pub use interpreter::*;
@@ -0,0 +1,854 @@
+#[cfg(test)]
+mod tests {
+    use crate::lexer::span::Span;
+    use crate::lexer::*;
+    use crate::parser::*;
+    use crate::runtime::*;
+
+    use std::rc::Rc;
+
+    // Evaluates `input` and renders either the value or the error message.
+    fn eval(input: &str) -> Result<String, String> {
+        let mut lexer = Lexer::new(input);
+        let toks = lexer.tokenize().unwrap();
+        let ast = AstParser::new(toks).with_source(input).parse().unwrap();
+        let mut interpreter = Interpreter::new(&ast);
+        interpreter
+            .interpret()
+            .and_then(|v| interpreter.force_deep(v))
+            .map(|v| v.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn eval_arithmetic() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("1 + 2", "3"),
+            ("1 + 2 * 3", "7"),
+            ("10 - 4 - 3", "3"),
+            ("7 / 2", "3"),
+            ("-7 / 2", "-3"),
+            ("7 / 2.0", "3.5"),
+            ("1.5 + 1", "2.5"),
+            ("2 * 0.25", "0.5"),
+            ("-(1 + 2)", "-3"),
+            ("(1 + 2) -1", "2"),
+            ("!true", "false"),
+            ("2147483647 + 1", "2147483648"),
+            ("65536 * 65536", "4294967296"),
+            ("1e3 + .5", "1000.5"),
+            ("2.5E-1 * 4", "1"),
+            ("1.0 / 3", "0.333333"),
+            ("123456.7 + 0", "123457"),
+            ("1000000.0 + 0", "1e+06"),
+            ("0.0001 + 0", "0.0001"),
+            ("-1.5e-5 + 0", "-1.5e-05"),
+            ("\"a\" + \"b\"", "\"ab\""),
+            ("\"a\" + /b", "\"a/b\""),
+            ("/a + /b", "/a/b"),
+            ("/a + \"/b\"", "/a/b"),
+            ("builtins.isPath (/a + \"b\")", "true"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_arithmetic() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("1 / 0", "division by zero, at 1:1"),
+            ("1.5 / 0", "division by zero, at 1:1"),
+            ("9223372036854775807 + 1", "integer overflow, at 1:1"),
+            ("4294967296 * 4294967296", "integer overflow, at 1:1"),
+            ("-(-9223372036854775807 - 1)", "integer overflow, at 1:1"),
+            (
+                "1 + true",
+                "operator + cannot be applied to int and bool, at 1:1",
+            ),
+            (
+                "null * 2",
+                "operator * cannot be applied to null and int, at 1:1",
+            ),
+            (
+                "\"a\" - \"b\"",
+                "operator - cannot be applied to string and string, at 1:1",
+            ),
+            (
+                "\"a\" + 1",
+                "operator + cannot be applied to string and int, at 1:1",
+            ),
+            ("-true", "expected number, found bool, at 1:2"),
+            ("!1", "expected bool, found int, at 1:2"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_comparisons() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("1 < 2", "true"),
+            ("2 <= 2", "true"),
+            ("3 > 2.5", "true"),
+            ("2.0 >= 2", "true"),
+            ("1 + 1 < 2", "false"),
+            ("\"abc\" < \"abd\"", "true"),
+            ("\"b\" > \"abc\"", "true"),
+            ("\"\" < \"a\"", "true"),
+            ("./a/b < ./a/c", "true"),
+            ("/etc >= /etc", "true"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_comparisons() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("1 < \"1\"", "cannot compare int with string, at 1:1"),
+            ("true > false", "cannot compare bool with bool, at 1:1"),
+            ("\"a\" <= ./a", "cannot compare string with path, at 1:1"),
+            ("(x: x) < 1", "cannot compare lambda with int, at 1:2"),
+            ("{ } < { }", "cannot compare set with set, at 1:1"),
+            (
+                "[ 1 ] < [ \"a\" ]",
+                "cannot compare int with string, at 1:1",
+            ),
+            (
+                "[ 1 ] ++ { }",
+                "operator ++ cannot be applied to list and set, at 1:1",
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_sets_and_lists() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("{ }", "{ }"),
+            ("[ ]", "[ ]"),
+            ("{ b = 1 + 1; a = \"x\"; }", "{ a = \"x\"; b = 2; }"),
+            (
+                "[ 1 (2 * 3) { a = [ true ]; } ]",
+                "[ 1 6 { a = [ true ]; } ]",
+            ),
+            ("[ 1 ] ++ [ 2 3 ]", "[ 1 2 3 ]"),
+            (
+                "{ a = 1; b = 2; } // { b = 3; c = 4; }",
+                "{ a = 1; b = 3; c = 4; }",
+            ),
+            ("{ a = [ 1 2 ]; } == { a = [ 1 2.0 ]; }", "true"),
+            ("{ a = 1; } == { b = 1; }", "false"),
+            ("[ 1 2 ] != [ 1 ]", "true"),
+            ("[ 1 2 ] < [ 1 3 ]", "true"),
+            ("[ 1 2 ] < [ 1 2 0 ]", "true"),
+            ("[ 2 ] >= [ 1 5 ]", "true"),
+            ("let x = { a = x; }; in x == x", "true"),
+            ("let x = [ x ]; in x != x", "false"),
+            ("let s = { f = x: x; }; in s == s", "true"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_set_members_lazily() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("{ a = 1 / 0; } != { }", "true"),
+            ("[ (1 / 0) ] == [ ]", "false"),
+            ("[ 1 (1 / 0) ] < [ 2 (1 / 0) ]", "true"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_only_what_is_needed() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            ("{ a = 1 / 0; b = 2; }.b", Ok("2")),
+            ("{ a = { b = 1 / 0; }; }.a == { }", Ok("false")),
+            ("{ a = 1 / 0; b = 2; }.a", Err("division by zero, at 1:7")),
+            ("{ a = 1; }.b", Err("attribute 'b' missing, at 1:1")),
+            ("{ a = 1; }.a.b", Err("expected set, found int, at 1:1")),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_let_bindings() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("let x = 1; in x", "1"),
+            ("let x = 1; y = x + 1; in [ x y ]", "[ 1 2 ]"),
+            ("let a = b * 2; b = 3; in a", "6"),
+            ("let x = 1 / 0; in 1", "1"),
+            ("let x = 1; in let x = 2; in x", "2"),
+            (
+                "let x = 1; in let y = x + 1; in { a = x; b = y; }",
+                "{ a = 1; b = 2; }",
+            ),
+            ("let s = { a = s0; }; s0 = 5; in s.a", "5"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_let_bindings() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("x", "undefined variable 'x', at 1:1"),
+            ("let x = 1; in\n  x + y", "undefined variable 'y', at 2:7"),
+            (
+                "let x = y; in let y = 1; in x",
+                "undefined variable 'y', at 1:9",
+            ),
+            ("let x = x; in x", "infinite recursion encountered, at 1:9"),
+            (
+                "let a = b; b = a + 1; in a",
+                "infinite recursion encountered, at 1:9",
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_rec_sets() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            ("rec { a = 1; b = a + 1; }", Ok("{ a = 1; b = 2; }")),
+            ("rec { b = a + 1; a = 1; }.b", Ok("2")),
+            ("let a = 10; in rec { a = 1; b = a; }.b", Ok("1")),
+            ("let c = 3; in rec { a = c; }", Ok("{ a = 3; }")),
+            ("{ a = 1; b = a; }", Err("undefined variable 'a', at 1:14")),
+            (
+                "rec { a = a; }",
+                Err("infinite recursion encountered, at 1:11"),
+            ),
+            (
+                "rec { a = b; b = a; }.b",
+                Err("infinite recursion encountered, at 1:18"),
+            ),
+            (
+                "rec { x = { y = x; }; }",
+                Ok("{ x = { y = «repeated»; }; }"),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_inherit() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            (
+                "let a = 1; b = 2; in { inherit a b; }",
+                Ok("{ a = 1; b = 2; }"),
+            ),
+            (
+                "let a = 1; in rec { inherit a; b = a + 1; }",
+                Ok("{ a = 1; b = 2; }"),
+            ),
+            ("let x = 1; in let inherit x; in x", Ok("1")),
+            (
+                "let src = { a = 1; b = 2; }; in { inherit (src) a b; }",
+                Ok("{ a = 1; b = 2; }"),
+            ),
+            ("let inherit ({ x = 3; }) x; y = x; in y", Ok("3")),
+            ("let src = { a = 1; }; inherit (src) a; in a", Ok("1")),
+            ("rec { inherit (s) a; s = { a = 1; }; }.a", Ok("1")),
+            ("{ inherit (throw \"x\") a; b = 2; }.b", Ok("2")),
+            (
+                "{ a.y = 2; a = { inherit ({ x = 1; }) x; }; }.a",
+                Ok("{ x = 1; y = 2; }"),
+            ),
+            // Evaluating `src` once per name would take 2^60 steps.
+            (
+                "let f = n: if n == 0 then { a = 1; b = 1; } else let inherit (f (n - 1)) a b; in { a = a + b; b = a + b; }; in (f 60).a",
+                Ok("1152921504606846976"),
+            ),
+            ("{ inherit a; }.a", Err("undefined variable 'a', at 1:11")),
+            (
+                "let src = { }; in { inherit (src) a; }.a",
+                Err("attribute 'a' missing, at 1:35"),
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_attr_paths() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            ("{ a.b.c = 1; }", Ok("{ a = { b = { c = 1; }; }; }")),
+            ("{ a.b = 1; a.c = 2; }.a", Ok("{ b = 1; c = 2; }")),
+            (
+                "{ a.b = 1; a = { c = 2; }; }",
+                Ok("{ a = { b = 1; c = 2; }; }"),
+            ),
+            ("{ \"foo-bar\".x = 1; }", Ok("{ foo-bar = { x = 1; }; }")),
+            (
+                "{ \"a\\tb\".c = 1; \"a\\tb\".d = 2; }",
+                Ok("{ \"a\\tb\" = { c = 1; d = 2; }; }"),
+            ),
+            ("rec { \"a\\q\" = 1; b = aq; }.b", Ok("1")),
+            ("let \"a\\q\" = 1; in aq", Ok("1")),
+            ("let a.b = 1; in a.b", Ok("1")),
+            ("rec { a.b = 1; c = a.b + 1; }.c", Ok("2")),
+            (
+                "let n = \"x\"; in { ${n} = 1; ${\"y\"}.z = 2; }",
+                Ok("{ x = 1; y = { z = 2; }; }"),
+            ),
+            ("{ ${null} = 1; }", Ok("{ }")),
+            (
+                "{ a = 1; ${\"a\"} = 2; }",
+                Err("dynamic attribute 'a' already defined, at 1:12"),
+            ),
+            ("{ ${1} = 2; }", Err("expected string, found int, at 1:5")),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_interpolation() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            (
+                "let name = \"nix\"; in \"hello ${name}!\"",
+                Ok("\"hello nix!\""),
+            ),
+            ("let a = \"b\"; in ''${a}${\"c${a}\"}''", Ok("\"bcb\"")),
+            ("\"${./foo}/bar\"", Ok("\"./foo/bar\"")),
+            (
+                "\"${{ outPath = \"/nix/store/x\"; }}\"",
+                Ok("\"/nix/store/x\""),
+            ),
+            ("{ \"a${\"b\"}\" = 1; }", Ok("{ ab = 1; }")),
+            ("\"${1}\"", Err("cannot coerce int to a string, at 1:4")),
+            ("\"${{ }}\"", Err("cannot coerce set to a string, at 1:4")),
+            ("\"${x}\"", Err("undefined variable 'x', at 1:4")),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_string_escapes() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            (r#""say \"hi\"\n""#, Ok(r#""say \"hi\"\n""#)),
+            (r#""\${x}""#, Ok(r#""\${x}""#)),
+            (
+                "let x = \"X\"; in ''\n    ${x}:\n      indented\n  ''",
+                Ok(r#""X:\n  indented\n""#),
+            ),
+            ("{ \"a\\\"b\" = 1; }", Ok(r#"{ "a\"b" = 1; }"#)),
+        ];
+
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+    }
+
+    #[test]
+    fn force_memoizes_thunks() {
+        let expr = Expr::new_add(Expr::new_int(1), Expr::new_int(2));
+        let env = Rc::new(Env::new(None, false));
+        let thunk = Rc::new(Thunk::new(expr.clone(), env));
+        let interpreter = Interpreter::new(&expr);
+
+        let got = interpreter.force(Value::Thunk(thunk.clone())).unwrap();
+        assert_eq!(got, Value::Int(3));
+        assert!(matches!(
+            &*thunk.state.borrow(),
+            ThunkState::Done(Value::Int(3))
+        ));
+        let got = interpreter.force(Value::Thunk(thunk)).unwrap();
+        assert_eq!(got, Value::Int(3));
+    }
+
+    #[test]
+    fn force_detects_infinite_recursion() {
+        // a = a, with the thunk for `a` stored in the scope it refers to.
+        let expr = Expr::new_ident("a");
+        let env = Rc::new(Env::new(None, false));
+        let thunk = Rc::new(Thunk::new(expr.clone(), env.clone()));
+        env.set(Rc::from("a"), Value::Thunk(thunk.clone())).unwrap();
+        let interpreter = Interpreter::new(&expr);
+
+        let err = interpreter.force(Value::Thunk(thunk.clone())).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvalError>().map(|e| &e.kind),
+            Some(&EvalErrorKind::InfiniteRecursion)
+        );
+        // A failed thunk can be forced again and fails the same way.
+        assert!(matches!(&*thunk.state.borrow(), ThunkState::Pending(..)));
+        assert_eq!(
+            interpreter
+                .force(Value::Thunk(thunk))
+                .unwrap_err()
+                .to_string(),
+            "infinite recursion encountered, at 0:0"
+        );
+    }
+
+    #[test]
+    fn eval_errors_are_typed() {
+        let input = "1 + 4 / 0";
+        let mut lexer = Lexer::new(input);
+        let toks = lexer.tokenize().unwrap();
+        let ast = AstParser::new(toks).parse().unwrap();
+        let mut interpreter = Interpreter::new(&ast);
+        let err = interpreter.interpret().unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref::<EvalError>(),
+            Some(&EvalError::new(
+                EvalErrorKind::DivisionByZero,
+                Span::new(4, 9, 1, 5)
+            ))
+        );
+    }
+
+    #[test]
+    fn eval_operator_precedence() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("true -> false", "false"),
+            ("false -> 1 / 0", "true"),
+            ("false -> false -> false", "true"),
+            ("true || false && false", "true"),
+            ("!true || true", "true"),
+            ("!false == true", "true"),
+            ("!true == !true", "true"),
+            ("false || !false && true", "true"),
+            ("1 + 2 == 3 && 2 * 2 < 5", "true"),
+            ("[ 1 ] ++ [ 2 ] ++ [ 3 ]", "[ 1 2 3 ]"),
+            (
+                "{ a = 1; } // { b = 2; } // { a = 3; }",
+                "{ a = 3; b = 2; }",
+            ),
+            ("-{ a = 1; }.a * 2", "-2"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_control_flow() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("if 1 < 2 then \"yes\" else \"no\"", "\"yes\""),
+            ("if false then 1 / 0 else 2", "2"),
+            ("with { a = 1; b = 2; }; a + b", "3"),
+            ("let a = 1; in with { a = 2; }; a", "1"),
+            ("with { a = 1; }; with { a = 2; }; a", "2"),
+            ("with { a = 1; }; let b = a + 1; in [ a b ]", "[ 1 2 ]"),
+            ("with throw \"x\"; 1", "1"),
+            ("with { a = 1; }; with { }; a", "1"),
+            ("{ a.b.c = 1; } ? a.b.c", "true"),
+            ("{ a.b = 1; } ? a.c", "false"),
+            ("{ a = 1; } ? a.b", "false"),
+            ("1 ? a", "false"),
+            ("{ \"x y\" = 1; } ? \"x y\"", "true"),
+            ("let n = \"a\"; in { a = 1; } ? ${n}", "true"),
+            ("assert 1 + 1 == 2; \"ok\"", "\"ok\""),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_control_flow() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("if 1 then 2 else 3", "expected bool, found int, at 1:4"),
+            ("with 1; x", "expected set, found int, at 1:6"),
+            (
+                "with { a = 1; }; with 2; a",
+                "expected set, found int, at 1:23",
+            ),
+            ("with throw \"x\"; y", "x, at 1:6"),
+            ("with {}; x", "undefined variable 'x', at 1:10"),
+            ("assert 1 == 2; 3", "assertion '1 == 2' failed, at 1:8"),
+            (
+                "let f = x: assert (x > 0); x; in f 0",
+                "assertion 'x > 0' failed, at 1:20",
+            ),
+            ("assert null; 3", "expected bool, found null, at 1:8"),
+            ("{ } ? ${1}", "expected string, found int, at 1:9"),
+            ("1 && true", "expected bool, found int, at 1:1"),
+            ("null -> true", "expected bool, found null, at 1:1"),
+            ("true && 1", "expected bool, found int, at 1:9"),
+            ("false || null", "expected bool, found null, at 1:10"),
+            ("true && throw \"boom\"", "boom, at 1:9"),
+            ("true -> nope", "undefined variable 'nope', at 1:9"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_selection() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("{ a.b.c = 1; }.a.b.c", "1"),
+            ("{ \"foo-bar\" = 1; }.\"foo-bar\"", "1"),
+            ("let n = \"b\"; in { a.b = 2; }.a.${n}", "2"),
+            ("let n = \"b\"; in { ab = 3; }.\"a${n}\"", "3"),
+            ("({ a = 1; } // { b = 2; }).b", "2"),
+            ("{ a = 1; }.b or 2", "2"),
+            ("{ a = 1; }.a or 2", "1"),
+            ("{ a = 1; }.a.b or 3", "3"),
+            ("let s = { x = 4; }; in { }.a or s.x", "4"),
+            ("let or = 5; in [ or ]", "[ 5 ]"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_selection() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("{ a = 1; }.b", "attribute 'b' missing, at 1:1"),
+            (
+                "{ foo = 1; }.fo",
+                "attribute 'fo' missing (did you mean 'foo'?), at 1:1",
+            ),
+            (
+                "{ bar = 1; baz = 2; bat = 3; qux = 4; }.ba",
+                "attribute 'ba' missing (did you mean one of 'bar', 'bat' or 'baz'?), at 1:1",
+            ),
+            ("{ a.b = 1; }.a.c", "attribute 'c' missing, at 1:1"),
+            ("{ a = 1; }.a.b", "expected set, found int, at 1:1"),
+            ("[ ].a", "expected set, found list, at 1:1"),
+            ("{ a = 1; }.\"\"", "attribute '' missing, at 1:1"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_application() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("(x: x + 1) 2", "3"),
+            ("let add = a: b: a + b; in add 1 2", "3"),
+            ("let add = a: b: a + b; inc = add 1; in [ (inc 1) (inc 2) ]", "[ 2 3 ]"),
+            ("let a = 1; f = x: a + x; in let a = 10; in f 1", "2"),
+            (
+                "let fac = n: if n == 0 then 1 else n * fac (n - 1); in fac 10",
+                "3628800",
+            ),
+            ("({ a, b ? a * 2 }: a + b) { a = 1; }", "3"),
+            ("({ a, ... }: a) { a = 1; b = 2; }", "1"),
+            ("(args@{ a, ... }: args.b) { a = 1; b = 2; }", "2"),
+            ("(x: y: x) 1 (1 / 0)", "1"),
+            (
+                "let s = { n = 2; __functor = self: x: self.n * x; }; in s 3",
+                "6",
+            ),
+            (
+                "let s = { n = 1; __toString = self: \"n=${toString self.n}\"; }; toString = x: \"1\"; in \"${s}\"",
+                "\"n=1\"",
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_application() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("let x = 1; in x 2", "expected function, found int, at 1:15"),
+            (
+                "({ a, b }: a) { a = 1; }",
+                "function called without required argument 'b', at 1:2",
+            ),
+            (
+                "({ a }: a) { a = 1; c = 2; }",
+                "function called with unexpected argument 'c', at 1:2",
+            ),
+            ("({ a }: a) 1", "expected set, found int, at 1:2"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_builtins() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (
+                "let t = builtins.typeOf; in [ (t 1) (t 1.5) (t \"a\") (t ./a) (t true) (t null) ]",
+                "[ \"int\" \"float\" \"string\" \"path\" \"bool\" \"null\" ]",
+            ),
+            (
+                "let t = builtins.typeOf; in [ (t [ ]) (t { }) (t (x: x)) (t t) ]",
+                "[ \"list\" \"set\" \"lambda\" \"lambda\" ]",
+            ),
+            ("builtins.typeOf (let x = 1; in x)", "\"int\""),
+            (
+                "[ (builtins.isInt 1) (builtins.isInt 1.0) (builtins.isFloat 1.0) ]",
+                "[ true false true ]",
+            ),
+            (
+                "[ (builtins.isString \"a\") (builtins.isBool false) (builtins.isNull null) ]",
+                "[ true true true ]",
+            ),
+            (
+                "[ (builtins.isList [ ]) (builtins.isAttrs { }) (builtins.isPath ./a) ]",
+                "[ true true true ]",
+            ),
+            (
+                "[ (builtins.isFunction (x: x)) (builtins.isFunction toString) (builtins.isFunction { }) ]",
+                "[ true true false ]",
+            ),
+            ("[ builtins.true builtins.false builtins.null ]", "[ true false null ]"),
+            (
+                "[ (toString 1) (toString 1.5) (toString true) (toString false) (toString null) ]",
+                "[ \"1\" \"1.500000\" \"1\" \"\" \"\" ]",
+            ),
+            ("toString [ 1 \"a\" [ 2 ] ]", "\"1 a 2\""),
+            ("toString { __toString = self: \"s\"; }", "\"s\""),
+            ("builtins.toString ./a", "\"./a\""),
+            ("let f = builtins.typeOf; in f 1", "\"int\""),
+            ("builtins.typeOf", "<PRIMOP>"),
+            ("let toString = x: x; in toString 1", "1"),
+            ("if true then 1 else throw \"no\"", "1"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_list_builtins() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("builtins.length [ 1 (1 / 0) 3 ]", "3"),
+            ("builtins.head [ 1 2 ]", "1"),
+            ("builtins.tail [ 1 2 3 ]", "[ 2 3 ]"),
+            ("builtins.elemAt [ 1 2 3 ] 2", "3"),
+            ("[ (builtins.elem 2 [ 1 2.0 ]) (builtins.elem 3 [ 1 2 ]) ]", "[ true false ]"),
+            ("map (x: x * 2) [ 1 2 3 ]", "[ 2 4 6 ]"),
+            ("builtins.map toString [ 1 ]", "[ \"1\" ]"),
+            ("builtins.length (map (x: 1 / x) [ 0 1 ])", "2"),
+            ("builtins.filter (x: x > 1) [ 1 2 3 ]", "[ 2 3 ]"),
+            ("builtins.foldl' (acc: x: acc + x) 0 [ 1 2 3 ]", "6"),
+            ("builtins.foldl' (acc: x: [ x ] ++ acc) [ ] [ 1 2 3 ]", "[ 3 2 1 ]"),
+            ("builtins.genList (i: i * i) 4", "[ 0 1 4 9 ]"),
+            ("builtins.elemAt (builtins.genList (i: 10 / i) 3) 2", "5"),
+            ("builtins.concatLists [ [ 1 ] [ ] [ 2 3 ] ]", "[ 1 2 3 ]"),
+            ("builtins.concatMap (x: [ x x ]) [ 1 2 ]", "[ 1 1 2 2 ]"),
+            (
+                "[ (builtins.all (x: x > 0) [ 1 2 ]) (builtins.all (x: x > 1) [ 1 2 ]) ]",
+                "[ true false ]",
+            ),
+            (
+                "[ (builtins.any (x: x > 1) [ 1 2 ]) (builtins.any (x: x > 2) [ ]) ]",
+                "[ true false ]",
+            ),
+            ("builtins.any (x: x == 1) [ 1 (1 / 0) ]", "true"),
+            ("builtins.sort (a: b: a < b) [ 3 1 2 1 ]", "[ 1 1 2 3 ]"),
+            (
+                "builtins.sort (a: b: a.k < b.k) [ { k = 2; v = 1; } { k = 1; v = 2; } { k = 2; v = 3; } ]",
+                "[ { k = 1; v = 2; } { k = 2; v = 1; } { k = 2; v = 3; } ]",
+            ),
+            (
+                "builtins.partition (x: x > 2) [ 1 3 2 4 ]",
+                "{ right = [ 3 4 ]; wrong = [ 1 2 ]; }",
+            ),
+            (
+                "builtins.groupBy (s: if s > \"b\" then \"late\" else \"early\") [ \"a\" \"c\" \"b\" ]",
+                "{ early = [ \"a\" \"b\" ]; late = [ \"c\" ]; }",
+            ),
+            (
+                "builtins.listToAttrs [ { name = \"a\"; value = 1; } { name = \"b\"; value = 2; } { name = \"a\"; value = 3; } ]",
+                "{ a = 1; b = 2; }",
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Ok(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_list_builtins() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            (
+                "builtins.head [ ]",
+                "'head' called on an empty list, at 1:1",
+            ),
+            (
+                "builtins.tail [ ]",
+                "'tail' called on an empty list, at 1:1",
+            ),
+            (
+                "builtins.elemAt [ 1 ] 1",
+                "list index 1 is out of bounds, at 1:1",
+            ),
+            (
+                "builtins.elemAt [ 1 ] (-1)",
+                "list index -1 is out of bounds, at 1:1",
+            ),
+            (
+                "builtins.genList (x: x) (-1)",
+                "cannot create list of size -1, at 1:1",
+            ),
+            ("builtins.length { }", "expected list, found set, at 1:1"),
+            (
+                "builtins.filter (x: 1) [ 1 ]",
+                "expected bool, found int, at 1:1",
+            ),
+            ("map 1 [ 1 ]", "expected function, found int, at 1:1"),
+            (
+                "builtins.sort (a: b: a < b) [ 1 \"a\" ]",
+                "cannot compare string with int, at 1:22",
+            ),
+            (
+                "builtins.listToAttrs [ { name = \"a\"; } ]",
+                "attribute 'value' missing, at 1:1",
+            ),
+            (
+                "builtins.groupBy (x: x) [ 1 ]",
+                "expected string, found int, at 1:1",
+            ),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_invalid_builtins() {
+        let test_cases: Vec<(&str, &str)> = vec![
+            ("throw \"oops\"", "oops, at 1:1"),
+            (
+                "abort \"oops\"",
+                "evaluation aborted with the following error message: 'oops', at 1:1",
+            ),
+            ("throw 1", "expected string, found int, at 1:1"),
+            (
+                "toString (x: x)",
+                "cannot coerce lambda to a string, at 1:1",
+            ),
+            ("toString { }", "cannot coerce set to a string, at 1:1"),
+            ("builtins.typeOf (throw \"inner\")", "inner, at 1:18"),
+            ("builtins.nope", "attribute 'nope' missing, at 1:1"),
+        ];
+
+        for (input, want) in test_cases {
+            assert_eq!(eval(input), Err(want.to_owned()), "{}", input);
+        }
+    }
+
+    #[test]
+    fn eval_limits_call_depth() {
+        let test_cases: Vec<(&str, Result<&str, &str>)> = vec![
+            (
+                "let f = n: if n == 0 then 0 else f (n - 1); in f 9000",
+                Ok("0"),
+            ),
+            (
+                "let f = x: f x; in f 1",
+                Err("stack overflow, max call depth of 10000 exceeded, at 1:12"),
+            ),
+            (
+                "let x = { a = x; }; y = { a = y; }; in x == y",
+                Err("stack overflow, max call depth of 10000 exceeded, at 1:40"),
+            ),
+            (
+                "let x = [ x ]; in x < x",
+                Err("stack overflow, max call depth of 10000 exceeded, at 1:19"),
+            ),
+            (
+                "builtins.length (builtins.foldl' (acc: x: [ acc ]) [ ] (builtins.genList (x: x) 20000))",
+                Ok("1"),
+            ),
+        ];
+
+        // Runs on the default test thread: the stack grows as needed.
+        for (input, want) in test_cases {
+            let want = want.map(str::to_owned).map_err(str::to_owned);
+            assert_eq!(eval(input), want, "{}", input);
+        }
+        let deep = "let f = n: if n == 0 then 0 else f (n - 1); in f 9999";
+        assert_eq!(crate::runtime::eval(deep).unwrap(), Value::Int(0));
+    }
+
+    #[test]
+    fn eval_deeply_nested_expressions() {
+        let depth = 20000;
+        let test_cases: Vec<(String, &str)> = vec![
+            (
+                format!("{}1{}", "(1 + ".repeat(depth), ")".repeat(depth)),
+                "20001",
+            ),
+            (format!("1{}", " + 1".repeat(4 * depth)), "80001"),
+            (format!("{}true", "!".repeat(depth)), "true"),
+        ];
+
+        // Parsing, evaluating, printing and dropping all run on the default
+        // test thread.
+        for (input, want) in test_cases {
+            let mut lexer = Lexer::new(&input);
+            let toks = lexer.tokenize().unwrap();
+            let ast = AstParser::new(toks).parse().unwrap();
+            assert!(!format!("{:?}", ast).is_empty());
+            assert_eq!(eval(&input), Ok(want.to_owned()));
+        }
+    }
+
+    #[test]
+    fn eval_values_outlive_source() {
+        let source = String::from(r#"let x = "b"; in [ { inherit x; s = "a${x}"; } (y: y) ]"#);
+        let value = crate::runtime::eval(&source).unwrap();
+        drop(source);
+
+        assert_eq!(value.to_string(), r#"[ { s = "ab"; x = "b"; } <LAMBDA> ]"#);
+        let err = crate::runtime::eval("{ a = 1; }.b").unwrap_err();
+        assert_eq!(err.to_string(), "attribute 'b' missing, at 1:1");
+    }
+}
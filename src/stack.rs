@@ -0,0 +1,11 @@
+// Once less than `STACK_RED_ZONE` bytes of stack are left, the recursion
+// continues on a newly allocated segment of `STACK_SEGMENT_SIZE` bytes.
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT_SIZE: usize = 4 * 1024 * 1024;
+
+/// Runs `f`, first moving to a fresh stack segment if the current one is
+/// nearly used up. Wraps every recursion on input of unbounded depth, from
+/// parsing to evaluating and dropping.
+pub(crate) fn grow_stack<T>(f: impl FnOnce() -> T) -> T {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, f)
+}